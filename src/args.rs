@@ -1,6 +1,11 @@
+use std::path::PathBuf;
+
 use anyhow::Result;
 use pico_args::Arguments;
 
+use crate::display::screenshot::ScreenshotFormat;
+use crate::terminal::cells::dump::DumpFormat;
+
 const HELP: &str = "\
 ft 0.1.0
 
@@ -11,10 +16,28 @@ Arguments:
   [SHELL]  Sets shell to run [default: /usr/bin/sh]
 
 Options:
-  -f, --font-path PATH       Sets font path [default: font.ttf]
-  -s, --font-size-px NUMBER  Sets font size [default: 16]
-  -d, --fb-device-path PATH  Sets framebuffer device path [default: /dev/fb0]
-  -h, --help                 Prints help information
+  -f, --font-path PATH            Sets font path [default: font.ttf]
+  -s, --font-size-px NUMBER       Sets font size [default: 16]
+  -d, --fb-device-path PATH       Sets framebuffer device path [default: /dev/fb0]
+  -b, --scrollback NUMBER         Sets number of lines kept in scrollback [default: 1000]
+  -p, --page-flipping             Draws to a hidden page of the virtual resolution and pans to it
+  -k, --kms-device PATH           Uses DRM/KMS device, like /dev/dri/card0, instead of framebuffer
+  -o, --screenshot-dir PATH       Sets directory of screenshots taken with Shift+F12 [default: .]
+  -t, --screenshot-format FORMAT  Sets format of screenshots, png or ppm [default: png]
+  -x, --screenshot-on-exit        Saves a screenshot once the shell exits
+  -O, --dump-dir PATH             Sets directory of text dumps taken with Shift+F11 [default: .]
+  -T, --dump-format FORMAT        Sets format of text dumps, plain, ansi or html [default: plain]
+  -B, --dump-scrollback           Includes scrollback in text dumps
+  -R, --dump-requests             Lets programs save text dumps with CSI i, once a second at most
+  -h, --help                      Prints help information
+
+Keys:
+  Shift+Home or Shift+F5          Scrolls viewport to the top of the scrollback
+  Shift+PageUp or Shift+F6        Scrolls viewport a page up
+  Shift+PageDown or Shift+F7      Scrolls viewport a page down
+  Shift+End or Shift+F8           Scrolls viewport to the bottom
+  Shift+F11 or Shift+F9           Saves a text dump
+  Shift+F12 or Shift+F10          Saves a screenshot
 ";
 
 #[derive(Debug)]
@@ -22,6 +45,16 @@ pub struct Args {
     pub font_path: String,
     pub font_size_px: u32,
     pub framebuffer_device_path: String,
+    pub page_flipping: bool,
+    pub kms_device_path: Option<String>,
+    pub scrollback_lines: usize,
+    pub screenshot_directory: PathBuf,
+    pub screenshot_format: ScreenshotFormat,
+    pub screenshot_on_exit: bool,
+    pub dump_directory: PathBuf,
+    pub dump_format: DumpFormat,
+    pub dump_scrollback: bool,
+    pub dump_requests: bool,
     pub shell_path: String,
 }
 
@@ -34,6 +67,10 @@ impl Args {
             std::process::exit(0);
         }
 
+        let page_flipping = pico_args.contains(["-p", "--page-flipping"]);
+        let screenshot_on_exit = pico_args.contains(["-x", "--screenshot-on-exit"]);
+        let dump_scrollback = pico_args.contains(["-B", "--dump-scrollback"]);
+        let dump_requests = pico_args.contains(["-R", "--dump-requests"]);
         let args = Self {
             font_path: pico_args
                 .opt_value_from_str(["-f", "--font-path"])?
@@ -44,6 +81,26 @@ impl Args {
             framebuffer_device_path: pico_args
                 .opt_value_from_str(["-d", "--fb-device-path"])?
                 .unwrap_or_else(|| "/dev/fb0".to_owned()),
+            page_flipping,
+            kms_device_path: pico_args.opt_value_from_str(["-k", "--kms-device"])?,
+            scrollback_lines: pico_args
+                .opt_value_from_str(["-b", "--scrollback"])?
+                .unwrap_or(1000),
+            screenshot_directory: pico_args
+                .opt_value_from_str(["-o", "--screenshot-dir"])?
+                .unwrap_or_else(|| PathBuf::from(".")),
+            screenshot_format: pico_args
+                .opt_value_from_str(["-t", "--screenshot-format"])?
+                .unwrap_or(ScreenshotFormat::Png),
+            screenshot_on_exit,
+            dump_directory: pico_args
+                .opt_value_from_str(["-O", "--dump-dir"])?
+                .unwrap_or_else(|| PathBuf::from(".")),
+            dump_format: pico_args
+                .opt_value_from_str(["-T", "--dump-format"])?
+                .unwrap_or(DumpFormat::Plain),
+            dump_scrollback,
+            dump_requests,
             shell_path: pico_args
                 .opt_free_from_str()?
                 .unwrap_or_else(|| "/usr/bin/sh".to_owned()),
@@ -5,8 +5,8 @@ impl Rgba {
     pub fn blend(&self, background: Rgb) -> Rgb {
         Rgb([
             Self::mix(self.0[0] as u32, background.0[0] as u32, self.0[3] as u32),
-            Self::mix(self.0[1] as u32, background.0[0] as u32, self.0[3] as u32),
-            Self::mix(self.0[2] as u32, background.0[0] as u32, self.0[3] as u32),
+            Self::mix(self.0[1] as u32, background.0[1] as u32, self.0[3] as u32),
+            Self::mix(self.0[2] as u32, background.0[2] as u32, self.0[3] as u32),
         ])
     }
 
@@ -42,11 +42,11 @@ impl Rgb {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Alpha(u8);
 
 impl Alpha {
-    pub fn new(alpha: u8) -> Self {
+    pub const fn new(alpha: u8) -> Self {
         Self(alpha)
     }
 }
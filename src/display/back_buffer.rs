@@ -0,0 +1,146 @@
+use std::ops::Range;
+
+use crate::color::Rgb;
+use crate::display::pixel::{DisplayPixel, PixelFormat};
+use crate::spatial::dimension::Dimensions;
+use crate::spatial::point::Point;
+use crate::spatial::rectangle::Rectangle;
+use crate::spatial::PixelsUnit;
+
+/// Off-screen copy of the frame, which is drawn into and then flushed to the display, so partially
+/// drawn content is never shown. Pixels are stored in the display format without any padding.
+#[derive(Debug)]
+pub struct BackBuffer {
+    bytes: Vec<u8>,
+    size: Dimensions<PixelsUnit>,
+    format: PixelFormat,
+}
+
+impl BackBuffer {
+    pub fn new(size: Dimensions<PixelsUnit>, format: PixelFormat) -> Self {
+        let length = size.width() as usize * size.height() as usize * format.bytes_per_pixel();
+        Self {
+            bytes: vec![0; length],
+            size,
+            format,
+        }
+    }
+
+    pub fn size(&self) -> Dimensions<PixelsUnit> {
+        self.size
+    }
+
+    pub fn pixel_mut(&mut self, pixel: Point<PixelsUnit>) -> DisplayPixel<'_> {
+        debug_assert!(self.size.contains(pixel));
+
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let index = self.size.vector_index(pixel) * bytes_per_pixel;
+        let bytes = &mut self.bytes[index..index + bytes_per_pixel];
+        DisplayPixel::new(bytes, self.format)
+    }
+
+    pub fn rgb(&self, pixel: Point<PixelsUnit>) -> Rgb {
+        debug_assert!(self.size.contains(pixel));
+
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let index = self.size.vector_index(pixel) * bytes_per_pixel;
+        self.format
+            .decode(&self.bytes[index..index + bytes_per_pixel])
+    }
+
+    /// Moves rows of pixels in the range up by the given distance, or down if it is negative. Rows
+    /// exposed at the other end are left unchanged.
+    pub fn scroll(&mut self, rows: Range<u32>, distance: i32) {
+        let rows = rows.start.min(rows.end) as usize..rows.end.min(self.size.height()) as usize;
+        let distance_length = distance.unsigned_abs() as usize;
+        if distance_length >= rows.len() {
+            return;
+        }
+
+        let row_length = self.row_length();
+        let (source, destination) = if distance > 0 {
+            (rows.start + distance_length..rows.end, rows.start)
+        } else {
+            (
+                rows.start..rows.end - distance_length,
+                rows.start + distance_length,
+            )
+        };
+        let source = source.start * row_length..source.end * row_length;
+        self.bytes.copy_within(source, destination * row_length);
+    }
+
+    /// Copies the given rectangles to the frame, which has rows of the given length in bytes and
+    /// shows the back buffer at the given offset.
+    pub fn copy_to(
+        &self,
+        frame: &mut [u8],
+        frame_row_length: usize,
+        offset: Point<PixelsUnit>,
+        rectangles: &[Rectangle<PixelsUnit>],
+    ) {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let row_length = self.row_length();
+        let width = self.size.width();
+        let height = self.size.height();
+        for rectangle in rectangles {
+            let origin = rectangle.origin();
+            let start = origin.horizontal_distance().min(width);
+            let end = (start + rectangle.size().width()).min(width);
+            let length = (end - start) as usize * bytes_per_pixel;
+            let top = origin.vertical_distance().min(height);
+            let bottom = (top + rectangle.size().height()).min(height);
+            for row in top..bottom {
+                let source = row as usize * row_length + start as usize * bytes_per_pixel;
+                let frame_row = (offset.vertical_distance() + row) as usize * frame_row_length;
+                let frame_column = (offset.horizontal_distance() + start) as usize;
+                let destination = frame_row + frame_column * bytes_per_pixel;
+                frame[destination..destination + length]
+                    .copy_from_slice(&self.bytes[source..source + length]);
+            }
+        }
+    }
+
+    fn row_length(&self) -> usize {
+        self.size.width() as usize * self.format.bytes_per_pixel()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use framebuffer::{Bitfield, VarScreeninfo};
+
+    use super::*;
+
+    fn rgb565() -> PixelFormat {
+        let bitfield = |offset, length| Bitfield {
+            offset,
+            length,
+            msb_right: 0,
+        };
+        let screen_info = VarScreeninfo {
+            bits_per_pixel: 16,
+            red: bitfield(11, 5),
+            green: bitfield(5, 6),
+            blue: bitfield(0, 5),
+            ..VarScreeninfo::default()
+        };
+        PixelFormat::from_screen_info(&screen_info).unwrap()
+    }
+
+    #[test]
+    fn test_copy_to() {
+        let mut back_buffer = BackBuffer::new(Dimensions::new(2, 2), rgb565());
+        back_buffer
+            .pixel_mut(Point::new(1, 1))
+            .set_rgb(Rgb::new(0xff, 0xff, 0xff));
+
+        // Frame of 4 pixels per row, showing the back buffer from the second row and column.
+        let mut frame = vec![0; 4 * 2 * 3];
+        let rectangle = Rectangle::new(Point::new(0, 0), Dimensions::new(2, 2));
+        back_buffer.copy_to(&mut frame, 4 * 2, Point::new(1, 1), &[rectangle]);
+        let mut expected = vec![0; 4 * 2 * 3];
+        expected[2 * 8 + 2 * 2..2 * 8 + 2 * 2 + 2].copy_from_slice(&[0xff, 0xff]);
+        assert_eq!(expected, frame);
+    }
+}
@@ -0,0 +1,339 @@
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use anyhow::{bail, Result};
+use framebuffer::Framebuffer;
+use nix::errno::Errno;
+
+use crate::display::back_buffer::BackBuffer;
+use crate::display::pixel::PixelFormat;
+use crate::display::Display;
+use crate::spatial::dimension::Dimensions;
+use crate::spatial::point::Point;
+use crate::spatial::rectangle::Rectangle;
+use crate::spatial::PixelsUnit;
+
+nix::ioctl_write_ptr!(wait_for_vsync, b'F', 0x20, u32);
+
+/// Display of the legacy framebuffer device, like `/dev/fb0`.
+#[derive(Debug)]
+pub struct FramebufferDisplay {
+    framebuffer: Framebuffer,
+    framebuffer_device_path: String,
+    back_buffer: BackBuffer,
+    /// Pages of the virtual resolution, one of which is displayed while the other is being drawn,
+    /// if page flipping is enabled.
+    pages: Option<Pages>,
+    /// Whether the driver supports waiting for the vertical blank after panning.
+    vertical_sync: bool,
+}
+
+impl FramebufferDisplay {
+    pub fn new(framebuffer_device_path: &str, page_flipping: bool) -> Result<Self> {
+        let (framebuffer, back_buffer) = Self::open_framebuffer(framebuffer_device_path)?;
+        let mut display = Self {
+            framebuffer,
+            framebuffer_device_path: framebuffer_device_path.to_owned(),
+            back_buffer,
+            pages: None,
+            vertical_sync: true,
+        };
+        if page_flipping && display.supports_page_flipping() {
+            display.pages = Some(Pages::new());
+        }
+        Ok(display)
+    }
+
+    fn supports_page_flipping(&self) -> bool {
+        let screen_info = &self.framebuffer.var_screen_info;
+        if screen_info.yres_virtual < 2 * screen_info.yres {
+            log::warn!(
+                "Virtual resolution {}x{} is too small for page flipping",
+                screen_info.xres_virtual,
+                screen_info.yres_virtual
+            );
+            return false;
+        }
+        // Drivers may report a virtual resolution larger than the memory they map.
+        let frame_length = self.framebuffer.frame.len();
+        if !self.layout().fits_two_pages(frame_length) {
+            log::warn!(
+                "Framebuffer memory of {} bytes is too small for page flipping",
+                frame_length
+            );
+            return false;
+        }
+        true
+    }
+
+    fn open_framebuffer(framebuffer_device_path: &str) -> Result<(Framebuffer, BackBuffer)> {
+        let framebuffer = Framebuffer::new(framebuffer_device_path)?;
+        let screen_info = &framebuffer.var_screen_info;
+        let pixel_format = PixelFormat::from_screen_info(screen_info)?;
+        log::debug!("Framebuffer pixel format: {:?}", pixel_format);
+
+        let line_length = framebuffer.fix_screen_info.line_length as usize;
+        let visible_width = (screen_info.xoffset + screen_info.xres) as usize;
+        if visible_width * pixel_format.bytes_per_pixel() > line_length {
+            bail!(
+                "Framebuffer line length {} is too small for {} pixels",
+                line_length,
+                visible_width
+            );
+        }
+        let visible_height = (screen_info.yoffset + screen_info.yres) as usize;
+        if visible_height * line_length > framebuffer.frame.len() {
+            bail!(
+                "Framebuffer memory is too small for {} lines",
+                visible_height
+            );
+        }
+
+        let size = Dimensions::new(screen_info.xres, screen_info.yres);
+        let back_buffer = BackBuffer::new(size, pixel_format);
+        Ok((framebuffer, back_buffer))
+    }
+
+    fn layout(&self) -> FrameLayout {
+        let screen_info = &self.framebuffer.var_screen_info;
+        FrameLayout {
+            line_length: self.framebuffer.fix_screen_info.line_length as usize,
+            xoffset: screen_info.xoffset,
+            yres: screen_info.yres,
+        }
+    }
+
+    /// Waits until the panned page is being displayed, so the other one can be drawn without
+    /// tearing. Drivers without support for it are only panned.
+    fn wait_for_vertical_sync(&mut self) -> Result<()> {
+        if !self.vertical_sync {
+            return Ok(());
+        }
+        let fd = self.framebuffer.device.as_raw_fd();
+        let screen = 0;
+        // SAFETY: The descriptor is an open framebuffer device and screen is a valid pointer.
+        match unsafe { wait_for_vsync(fd, &screen) } {
+            Ok(_) => Ok(()),
+            Err(Errno::ENOTTY | Errno::EINVAL) => {
+                log::debug!("Framebuffer does not support waiting for vertical sync");
+                self.vertical_sync = false;
+                Ok(())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// Layout of the framebuffer memory, which may hold lines longer than the visible resolution and
+/// more of them.
+#[derive(Debug, Copy, Clone)]
+struct FrameLayout {
+    /// Length of a line in bytes.
+    line_length: usize,
+    /// Horizontal pan offset of the visible resolution.
+    xoffset: u32,
+    /// Height of the visible resolution, which is the height of each page.
+    yres: u32,
+}
+
+impl FrameLayout {
+    /// Returns true if the frame of the given length holds two pages, one below the other.
+    fn fits_two_pages(&self, frame_length: usize) -> bool {
+        2 * self.yres as usize * self.line_length <= frame_length
+    }
+
+    /// Copies rectangles of the back buffer to the page starting at the given line.
+    fn copy(
+        &self,
+        back_buffer: &BackBuffer,
+        frame: &mut [u8],
+        yoffset: u32,
+        rectangles: &[Rectangle<PixelsUnit>],
+    ) {
+        let offset = Point::new(self.xoffset, yoffset);
+        back_buffer.copy_to(frame, self.line_length, offset, rectangles);
+    }
+}
+
+/// Two pages of the virtual resolution, the second one right below the first one.
+#[derive(Debug)]
+struct Pages {
+    /// Rectangles flushed to the displayed page, which the hidden page is missing.
+    previous_rectangles: Vec<Rectangle<PixelsUnit>>,
+}
+
+impl Pages {
+    fn new() -> Self {
+        Self {
+            previous_rectangles: Vec::new(),
+        }
+    }
+
+    /// Copies the rectangles to the page hidden at the given vertical pan offset and returns its
+    /// offset to pan to, unless there is nothing to copy.
+    fn draw_hidden_page(
+        &mut self,
+        back_buffer: &BackBuffer,
+        frame: &mut [u8],
+        layout: FrameLayout,
+        yoffset: u32,
+        rectangles: &[Rectangle<PixelsUnit>],
+    ) -> Option<u32> {
+        if rectangles.is_empty() {
+            return None;
+        }
+
+        let hidden_page_yoffset = if yoffset >= layout.yres {
+            0
+        } else {
+            layout.yres
+        };
+        // Hidden page was displayed before the previous flush, so it misses its rectangles too.
+        let previous_rectangles = mem::replace(&mut self.previous_rectangles, rectangles.to_vec());
+        layout.copy(
+            back_buffer,
+            frame,
+            hidden_page_yoffset,
+            &previous_rectangles,
+        );
+        layout.copy(back_buffer, frame, hidden_page_yoffset, rectangles);
+        Some(hidden_page_yoffset)
+    }
+}
+
+impl Display for FramebufferDisplay {
+    fn back_buffer(&self) -> &BackBuffer {
+        &self.back_buffer
+    }
+
+    fn back_buffer_mut(&mut self) -> &mut BackBuffer {
+        &mut self.back_buffer
+    }
+
+    /// Copies the given rectangles of the back buffer to the framebuffer. With page flipping they
+    /// are copied to the hidden page, which is displayed afterwards.
+    fn flush(&mut self, rectangles: &[Rectangle<PixelsUnit>]) -> Result<()> {
+        let layout = self.layout();
+        let yoffset = self.framebuffer.var_screen_info.yoffset;
+        let frame = &mut self.framebuffer.frame;
+        let Some(pages) = &mut self.pages else {
+            layout.copy(&self.back_buffer, frame, yoffset, rectangles);
+            return Ok(());
+        };
+
+        let Some(hidden_page_yoffset) =
+            pages.draw_hidden_page(&self.back_buffer, frame, layout, yoffset, rectangles)
+        else {
+            return Ok(());
+        };
+        self.framebuffer.var_screen_info.yoffset = hidden_page_yoffset;
+        Framebuffer::pan_display(&self.framebuffer.device, &self.framebuffer.var_screen_info)?;
+        self.wait_for_vertical_sync()
+    }
+
+    /// Opens the framebuffer device again to map it with its current resolution.
+    fn reload(&mut self) -> Result<()> {
+        let (framebuffer, back_buffer) = Self::open_framebuffer(&self.framebuffer_device_path)?;
+        self.framebuffer = framebuffer;
+        self.back_buffer = back_buffer;
+        if self.pages.is_some() {
+            self.pages = self.supports_page_flipping().then(Pages::new);
+        }
+        Ok(())
+    }
+
+    fn device_fd(&self) -> Option<RawFd> {
+        Some(self.framebuffer.device.as_raw_fd())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Rgb;
+    use crate::display::memory::{MemoryDisplay, MemoryFrame};
+    use crate::spatial::dimension::Dimensions;
+
+    const BYTES_PER_PIXEL: usize = 4;
+
+    /// Returns true if the page starting at the given line shows the same pixels as the frame of
+    /// the memory display.
+    fn page_matches(
+        frame: &[u8],
+        layout: FrameLayout,
+        yoffset: u32,
+        expected: &MemoryFrame,
+    ) -> bool {
+        let size = expected.size();
+        (0..size.height()).all(|vertical_distance| {
+            (0..size.width()).all(|horizontal_distance| {
+                let line = (yoffset + vertical_distance) as usize;
+                let column = (layout.xoffset + horizontal_distance) as usize;
+                let index = line * layout.line_length + column * BYTES_PER_PIXEL;
+                let rgb = Rgb::new(frame[index + 2], frame[index + 1], frame[index]);
+                let point = Point::new(horizontal_distance, vertical_distance);
+                rgb == expected.rgb(point)
+            })
+        })
+    }
+
+    #[test]
+    fn test_draw_hidden_page() {
+        let size = Dimensions::new(3, 2);
+        let mut display = MemoryDisplay::new(size);
+        let expected = display.frame();
+        // Lines are padded past the visible resolution, which is panned by a pixel to the right.
+        let layout = FrameLayout {
+            line_length: 5 * BYTES_PER_PIXEL,
+            xoffset: 1,
+            yres: size.height(),
+        };
+        let mut frame = vec![0; layout.line_length * 2 * size.height() as usize];
+        let mut pages = Pages::new();
+
+        let first = Rectangle::new(Point::new(0, 0), Dimensions::new(1, 1));
+        display
+            .pixel_mut(Point::new(0, 0))
+            .set_rgb(Rgb::new(0xff, 0, 0));
+        display.flush(&[first]).unwrap();
+        let back_buffer = display.back_buffer();
+        let yoffset = pages.draw_hidden_page(back_buffer, &mut frame, layout, 0, &[first]);
+        assert_eq!(Some(2), yoffset);
+        assert!(page_matches(&frame, layout, 2, &expected));
+
+        let second = Rectangle::new(Point::new(2, 1), Dimensions::new(1, 1));
+        display
+            .pixel_mut(Point::new(2, 1))
+            .set_rgb(Rgb::new(0, 0xff, 0));
+        display.flush(&[second]).unwrap();
+        let back_buffer = display.back_buffer();
+        let yoffset = pages.draw_hidden_page(back_buffer, &mut frame, layout, 2, &[second]);
+        // First page gets the rectangle flushed to the second one as well.
+        assert_eq!(Some(0), yoffset);
+        assert!(page_matches(&frame, layout, 0, &expected));
+        assert!(!page_matches(&frame, layout, 2, &expected));
+
+        // Nothing changed, so there is nothing to pan to.
+        let back_buffer = display.back_buffer();
+        assert_eq!(
+            None,
+            pages.draw_hidden_page(back_buffer, &mut frame, layout, 0, &[])
+        );
+        // Columns left of the panned resolution are left untouched.
+        let mut skipped = (0..4).map(|line| line * layout.line_length);
+        assert!(skipped.all(|index| frame[index..index + BYTES_PER_PIXEL] == [0; 4]));
+    }
+
+    #[test]
+    fn test_fits_two_pages() {
+        let layout = FrameLayout {
+            line_length: 5 * BYTES_PER_PIXEL,
+            xoffset: 1,
+            yres: 2,
+        };
+        assert!(layout.fits_two_pages(layout.line_length * 4));
+        // Second page would end past the frame, so drawing it would index out of bounds.
+        assert!(!layout.fits_two_pages(layout.line_length * 4 - 1));
+        assert!(!layout.fits_two_pages(layout.line_length * 3));
+    }
+}
@@ -0,0 +1,422 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+
+use anyhow::{bail, Result};
+use drm::buffer::{Buffer, DrmFourcc};
+use drm::control::dumbbuffer::DumbBuffer;
+use drm::control::{
+    connector, crtc, framebuffer, Device as ControlDevice, Event, Mode, ModeTypeFlags,
+    PageFlipFlags, ResourceHandles,
+};
+use drm::Device;
+
+use crate::display::back_buffer::BackBuffer;
+use crate::display::pixel::PixelFormat;
+use crate::display::Display;
+use crate::spatial::dimension::Dimensions;
+use crate::spatial::point::Point;
+use crate::spatial::rectangle::Rectangle;
+use crate::spatial::PixelsUnit;
+
+/// Display of a DRM/KMS device, like `/dev/dri/card0`. It shows one of two dumb buffers, while
+/// the other one is drawn, and flips them on vertical blanking.
+#[derive(Debug)]
+pub struct KmsDisplay {
+    card: Card,
+    crtc: crtc::Handle,
+    /// State of the CRTC before it was taken over, which is restored when the display is dropped.
+    saved_crtc: SavedCrtc,
+    back_buffer: BackBuffer,
+    pages: [Page; 2],
+    flips: Flips,
+}
+
+impl KmsDisplay {
+    pub fn new(card_device_path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(card_device_path)?;
+        let card = Card(file);
+
+        let resources = card.resource_handles()?;
+        let (connector, mode) = Self::find_connector(&card, &resources)?;
+        let crtc = Self::find_crtc(&card, &resources, &connector)?;
+        log::debug!("Using KMS mode {:?} on CRTC {:?}", mode, crtc);
+
+        let (width, height) = mode.size();
+        let size = Dimensions::new(width.into(), height.into());
+        let pages = [Page::new(&card, size)?, Page::new(&card, size)?];
+        let saved_crtc = SavedCrtc::new(card.get_crtc(crtc)?, connector.handle());
+        card.set_crtc(
+            crtc,
+            Some(pages[0].framebuffer),
+            (0, 0),
+            &[connector.handle()],
+            Some(mode),
+        )?;
+
+        Ok(Self {
+            card,
+            crtc,
+            saved_crtc,
+            back_buffer: BackBuffer::new(size, PixelFormat::xrgb8888()),
+            pages,
+            flips: Flips::new(),
+        })
+    }
+
+    /// Returns the first connected connector together with its preferred mode.
+    fn find_connector(card: &Card, resources: &ResourceHandles) -> Result<(connector::Info, Mode)> {
+        for &handle in resources.connectors() {
+            let connector = card.get_connector(handle, true)?;
+            if connector.state() != connector::State::Connected {
+                continue;
+            }
+            let modes = connector.modes();
+            let preferred_mode = modes
+                .iter()
+                .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+                .or_else(|| modes.first());
+            if let Some(&mode) = preferred_mode {
+                return Ok((connector, mode));
+            }
+        }
+        bail!("No connected connector with any mode found")
+    }
+
+    /// Returns the CRTC currently driving the connector, or the first one able to drive it.
+    fn find_crtc(
+        card: &Card,
+        resources: &ResourceHandles,
+        connector: &connector::Info,
+    ) -> Result<crtc::Handle> {
+        if let Some(encoder) = connector.current_encoder() {
+            if let Some(crtc) = card.get_encoder(encoder)?.crtc() {
+                return Ok(crtc);
+            }
+        }
+        for &encoder in connector.encoders() {
+            let encoder = card.get_encoder(encoder)?;
+            if let Some(&crtc) = resources.filter_crtcs(encoder.possible_crtcs()).first() {
+                return Ok(crtc);
+            }
+        }
+        bail!("No CRTC found for connector {:?}", connector.handle())
+    }
+
+    /// Copies rectangles of the flip to its page and schedules the flip to it.
+    fn flip(&mut self, flip: Flip) -> Result<()> {
+        let page = &mut self.pages[flip.page];
+        let pitch = page.buffer.pitch() as usize;
+        let mut mapping = self.card.map_dumb_buffer(&mut page.buffer)?;
+        self.back_buffer
+            .copy_to(&mut mapping, pitch, Point::new(0, 0), &flip.rectangles);
+        drop(mapping);
+
+        self.card
+            .page_flip(self.crtc, page.framebuffer, PageFlipFlags::EVENT, None)?;
+        Ok(())
+    }
+}
+
+impl Display for KmsDisplay {
+    fn back_buffer(&self) -> &BackBuffer {
+        &self.back_buffer
+    }
+
+    fn back_buffer_mut(&mut self) -> &mut BackBuffer {
+        &mut self.back_buffer
+    }
+
+    /// Shows the rectangles with the next flip. If a flip is pending already, they wait for it
+    /// to complete, as only one flip can be scheduled at a time.
+    fn flush(&mut self, rectangles: &[Rectangle<PixelsUnit>]) -> Result<()> {
+        match self.flips.flush(rectangles) {
+            Some(flip) => self.flip(flip),
+            None => Ok(()),
+        }
+    }
+
+    /// Mode is chosen when the device is opened and does not change with the console, so there
+    /// is nothing to reload.
+    fn reload(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn device_fd(&self) -> Option<RawFd> {
+        Some(self.card.0.as_raw_fd())
+    }
+
+    fn event_fd(&self) -> Option<RawFd> {
+        self.device_fd()
+    }
+
+    /// Completes pending flips and shows rectangles queued in the meantime.
+    fn handle_events(&mut self) -> Result<()> {
+        let mut flip = None;
+        for event in self.card.receive_events()? {
+            if let Event::PageFlip(_) = event {
+                flip = self.flips.complete();
+            }
+        }
+        match flip {
+            Some(flip) => self.flip(flip),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for KmsDisplay {
+    fn drop(&mut self) {
+        // Pages are destroyed only afterwards, so the CRTC never scans out a destroyed one.
+        if let Err(error) = self.saved_crtc.restore(&self.card) {
+            log::warn!("Restoring CRTC failed: {}", error);
+        }
+
+        for page in &self.pages {
+            let result = self
+                .card
+                .destroy_framebuffer(page.framebuffer)
+                .and_then(|_| self.card.destroy_dumb_buffer(page.buffer));
+            if let Err(error) = result {
+                log::warn!("Destroying KMS buffer failed: {}", error);
+            }
+        }
+    }
+}
+
+/// Bookkeeping of flips between the two pages, which only one can be pending at a time.
+#[derive(Debug)]
+struct Flips {
+    /// Index of the page which is displayed, or will be once the pending flip completes.
+    front_page: usize,
+    pending: bool,
+    /// Rectangles flushed to the front page, which the other page is missing.
+    previous_rectangles: Vec<Rectangle<PixelsUnit>>,
+    /// Rectangles flushed while a flip was pending, which are shown once it completes.
+    queued_rectangles: Vec<Rectangle<PixelsUnit>>,
+}
+
+/// Flip to the page, which has to be updated with the rectangles first.
+#[derive(Debug, PartialEq)]
+struct Flip {
+    page: usize,
+    rectangles: Vec<Rectangle<PixelsUnit>>,
+}
+
+impl Flips {
+    fn new() -> Self {
+        Self {
+            front_page: 0,
+            pending: false,
+            previous_rectangles: Vec::new(),
+            queued_rectangles: Vec::new(),
+        }
+    }
+
+    /// Returns the flip showing the rectangles, unless they have to wait for the pending flip or
+    /// there are none.
+    fn flush(&mut self, rectangles: &[Rectangle<PixelsUnit>]) -> Option<Flip> {
+        if self.pending {
+            self.queued_rectangles.extend_from_slice(rectangles);
+            return None;
+        }
+        if rectangles.is_empty() {
+            return None;
+        }
+
+        let page = 1 - self.front_page;
+        // Back page was displayed before the previous flip, so it misses its rectangles too.
+        let mut flip_rectangles = mem::replace(&mut self.previous_rectangles, rectangles.to_vec());
+        flip_rectangles.extend_from_slice(rectangles);
+        self.front_page = page;
+        self.pending = true;
+        Some(Flip {
+            page,
+            rectangles: flip_rectangles,
+        })
+    }
+
+    /// Completes the pending flip and returns the next one showing the queued rectangles.
+    fn complete(&mut self) -> Option<Flip> {
+        self.pending = false;
+        let rectangles = mem::take(&mut self.queued_rectangles);
+        self.flush(&rectangles)
+    }
+}
+
+/// Configuration of the CRTC before the display took it over.
+#[derive(Debug)]
+struct SavedCrtc {
+    crtc: crtc::Handle,
+    connector: connector::Handle,
+    framebuffer: Option<framebuffer::Handle>,
+    position: (u32, u32),
+    mode: Option<Mode>,
+}
+
+impl SavedCrtc {
+    fn new(info: crtc::Info, connector: connector::Handle) -> Self {
+        Self {
+            crtc: info.handle(),
+            connector,
+            framebuffer: info.framebuffer(),
+            position: info.position(),
+            mode: info.mode(),
+        }
+    }
+
+    fn restore(&self, device: &impl ModeSetting) -> io::Result<()> {
+        device.configure_crtc(
+            self.crtc,
+            self.framebuffer,
+            self.position,
+            &[self.connector],
+            self.mode,
+        )
+    }
+}
+
+/// Configuration of the CRTC, which tests replace with a fake device.
+trait ModeSetting {
+    fn configure_crtc(
+        &self,
+        crtc: crtc::Handle,
+        framebuffer: Option<framebuffer::Handle>,
+        position: (u32, u32),
+        connectors: &[connector::Handle],
+        mode: Option<Mode>,
+    ) -> io::Result<()>;
+}
+
+/// Dumb buffer, which is registered as a framebuffer to be scanned out.
+#[derive(Debug)]
+struct Page {
+    buffer: DumbBuffer,
+    framebuffer: framebuffer::Handle,
+}
+
+impl Page {
+    fn new(card: &Card, size: Dimensions<PixelsUnit>) -> Result<Self> {
+        let size = (size.width(), size.height());
+        let buffer = card.create_dumb_buffer(size, DrmFourcc::Xrgb8888, 32)?;
+        let framebuffer = card.add_framebuffer(&buffer, 24, 32)?;
+        Ok(Self {
+            buffer,
+            framebuffer,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Card(File);
+
+impl AsFd for Card {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl Device for Card {}
+
+impl ControlDevice for Card {}
+
+impl ModeSetting for Card {
+    fn configure_crtc(
+        &self,
+        crtc: crtc::Handle,
+        framebuffer: Option<framebuffer::Handle>,
+        position: (u32, u32),
+        connectors: &[connector::Handle],
+        mode: Option<Mode>,
+    ) -> io::Result<()> {
+        self.set_crtc(crtc, framebuffer, position, connectors, mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    fn rectangle(horizontal_distance: u32) -> Rectangle<PixelsUnit> {
+        Rectangle::new(Point::new(horizontal_distance, 0), Dimensions::new(1, 1))
+    }
+
+    #[test]
+    fn test_flips() {
+        let mut flips = Flips::new();
+        assert_eq!(None, flips.flush(&[]));
+        let flip = Flip {
+            page: 1,
+            rectangles: vec![rectangle(0)],
+        };
+        assert_eq!(Some(flip), flips.flush(&[rectangle(0)]));
+
+        // Only one flip can be pending, so the rectangles wait for it.
+        assert_eq!(None, flips.flush(&[rectangle(1)]));
+        assert_eq!(None, flips.flush(&[rectangle(2)]));
+        let flip = Flip {
+            page: 0,
+            rectangles: vec![rectangle(0), rectangle(1), rectangle(2)],
+        };
+        assert_eq!(Some(flip), flips.complete());
+
+        assert_eq!(None, flips.complete());
+        let flip = Flip {
+            page: 1,
+            rectangles: vec![rectangle(1), rectangle(2), rectangle(3)],
+        };
+        assert_eq!(Some(flip), flips.flush(&[rectangle(3)]));
+    }
+
+    /// Framebuffer and position the CRTC is configured with.
+    type Configuration = (Option<framebuffer::Handle>, (u32, u32));
+
+    /// Device recording configurations of the CRTC.
+    #[derive(Default)]
+    struct FakeDevice {
+        configurations: RefCell<Vec<Configuration>>,
+    }
+
+    impl ModeSetting for FakeDevice {
+        fn configure_crtc(
+            &self,
+            _crtc: crtc::Handle,
+            framebuffer: Option<framebuffer::Handle>,
+            position: (u32, u32),
+            connectors: &[connector::Handle],
+            _mode: Option<Mode>,
+        ) -> io::Result<()> {
+            assert_eq!(1, connectors.len());
+            self.configurations
+                .borrow_mut()
+                .push((framebuffer, position));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_restore_crtc() {
+        let handle = NonZeroU32::new(1).unwrap();
+        let framebuffer = framebuffer::Handle::from(handle);
+        let saved_crtc = SavedCrtc {
+            crtc: crtc::Handle::from(handle),
+            connector: connector::Handle::from(handle),
+            framebuffer: Some(framebuffer),
+            position: (0, 8),
+            mode: None,
+        };
+        let device = FakeDevice::default();
+        saved_crtc.restore(&device).unwrap();
+        assert_eq!(
+            vec![(Some(framebuffer), (0, 8))],
+            *device.configurations.borrow()
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::os::unix::io::RawFd;
+use std::rc::Rc;
+
+use anyhow::Result;
+
+use crate::color::Rgb;
+use crate::display::back_buffer::BackBuffer;
+use crate::display::pixel::PixelFormat;
+use crate::display::Display;
+use crate::spatial::dimension::Dimensions;
+use crate::spatial::point::Point;
+use crate::spatial::rectangle::Rectangle;
+use crate::spatial::PixelsUnit;
+
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Display without any device, which flushes to a frame in memory. It lets the whole rendering run
+/// in tests, which check the flushed pixels through the shared frame.
+#[derive(Debug)]
+pub struct MemoryDisplay {
+    back_buffer: BackBuffer,
+    frame: MemoryFrame,
+}
+
+impl MemoryDisplay {
+    pub fn new(size: Dimensions<PixelsUnit>) -> Self {
+        Self {
+            back_buffer: BackBuffer::new(size, PixelFormat::xrgb8888()),
+            frame: MemoryFrame::new(size),
+        }
+    }
+
+    /// Returns the frame, which stays shared with the display after it is moved to the renderer.
+    pub fn frame(&self) -> MemoryFrame {
+        self.frame.clone()
+    }
+}
+
+impl Display for MemoryDisplay {
+    fn back_buffer(&self) -> &BackBuffer {
+        &self.back_buffer
+    }
+
+    fn back_buffer_mut(&mut self) -> &mut BackBuffer {
+        &mut self.back_buffer
+    }
+
+    fn flush(&mut self, rectangles: &[Rectangle<PixelsUnit>]) -> Result<()> {
+        let row_length = self.frame.size.width() as usize * BYTES_PER_PIXEL;
+        let mut bytes = self.frame.bytes.borrow_mut();
+        self.back_buffer
+            .copy_to(&mut bytes, row_length, Point::new(0, 0), rectangles);
+        Ok(())
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn device_fd(&self) -> Option<RawFd> {
+        None
+    }
+}
+
+/// Pixels flushed to the memory display, stored as XRGB8888.
+#[derive(Debug, Clone)]
+pub struct MemoryFrame {
+    bytes: Rc<RefCell<Vec<u8>>>,
+    size: Dimensions<PixelsUnit>,
+}
+
+impl MemoryFrame {
+    fn new(size: Dimensions<PixelsUnit>) -> Self {
+        let length = size.width() as usize * size.height() as usize * BYTES_PER_PIXEL;
+        Self {
+            bytes: Rc::new(RefCell::new(vec![0; length])),
+            size,
+        }
+    }
+
+    pub fn size(&self) -> Dimensions<PixelsUnit> {
+        self.size
+    }
+
+    pub fn rgb(&self, pixel: Point<PixelsUnit>) -> Rgb {
+        let index = self.size.vector_index(pixel) * BYTES_PER_PIXEL;
+        let bytes = self.bytes.borrow();
+        Rgb::new(bytes[index + 2], bytes[index + 1], bytes[index])
+    }
+
+    /// Returns true if all pixels of the rectangle have the given color.
+    pub fn is_filled(&self, rectangle: Rectangle<PixelsUnit>, rgb: Rgb) -> bool {
+        let origin = rectangle.origin();
+        let size = rectangle.size();
+        (0..size.height()).all(|vertical_distance| {
+            (0..size.width()).all(|horizontal_distance| {
+                let point = Point::new(horizontal_distance, vertical_distance);
+                self.rgb(point.with_origin(origin)) == rgb
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flush() {
+        let mut display = MemoryDisplay::new(Dimensions::new(4, 3));
+        let frame = display.frame();
+        let red = Rgb::new(0xff, 0, 0);
+        display.pixel_mut(Point::new(1, 1)).set_rgb(red);
+        display.pixel_mut(Point::new(3, 2)).set_rgb(red);
+
+        let rectangle = Rectangle::new(Point::new(0, 0), Dimensions::new(2, 2));
+        display.flush(&[rectangle]).unwrap();
+        assert_eq!(red, frame.rgb(Point::new(1, 1)));
+        assert_eq!(Rgb::new(0, 0, 0), frame.rgb(Point::new(3, 2)));
+    }
+}
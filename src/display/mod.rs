@@ -1,63 +1,61 @@
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::slice;
+use std::fmt::Debug;
+use std::ops::Range;
+use std::os::unix::io::RawFd;
 
 use anyhow::Result;
-use framebuffer::Framebuffer;
 
+use crate::display::back_buffer::BackBuffer;
 use crate::display::pixel::DisplayPixel;
 use crate::spatial::dimension::Dimensions;
 use crate::spatial::point::Point;
+use crate::spatial::rectangle::Rectangle;
 use crate::spatial::PixelsUnit;
 
+pub mod back_buffer;
+pub mod fbdev;
+pub mod kms;
+#[cfg(test)]
+pub mod memory;
 pub mod pixel;
+pub mod screenshot;
 
-#[derive(Debug)]
-pub struct Display {
-    framebuffer: Framebuffer,
-}
+/// Device the terminal is drawn on. Drawing goes to the back buffer, which is shown by flushing
+/// it to the device.
+pub trait Display: Debug {
+    fn back_buffer(&self) -> &BackBuffer;
 
-impl Display {
-    pub fn new(framebuffer_device_path: &str) -> Result<Self> {
-        let framebuffer = Framebuffer::new(framebuffer_device_path)?;
+    fn back_buffer_mut(&mut self) -> &mut BackBuffer;
 
-        // Only 4 bytes per pixel are supported.
-        assert_eq!(32, framebuffer.var_screen_info.bits_per_pixel);
-        // Only BGR0 pixel format is supported.
-        assert_eq!(16, framebuffer.var_screen_info.red.offset);
-        assert_eq!(8, framebuffer.var_screen_info.green.offset);
-        assert_eq!(0, framebuffer.var_screen_info.blue.offset);
-        assert_eq!(0, framebuffer.var_screen_info.transp.length);
+    /// Shows the given rectangles of the back buffer.
+    fn flush(&mut self, rectangles: &[Rectangle<PixelsUnit>]) -> Result<()>;
 
-        Ok(Self { framebuffer })
-    }
+    /// Picks up a changed resolution of the device, replacing the back buffer.
+    fn reload(&mut self) -> Result<()>;
 
-    pub fn pixel_mut(&mut self, pixel: Point<PixelsUnit>) -> DisplayPixel {
-        let size = self.size();
-        debug_assert!(size.contains(pixel));
+    /// Returns descriptor of the opened device, if there is any, which must not leak to the shell.
+    fn device_fd(&self) -> Option<RawFd>;
 
-        let index = size.vector_index(pixel);
-        let pixel = &mut self.pixel_chunks_mut()[index];
-        DisplayPixel::from_frame_chunk(pixel)
+    /// Returns descriptor to wait on for events of the display, if it has any.
+    fn event_fd(&self) -> Option<RawFd> {
+        None
     }
 
-    pub fn pixel_chunks_mut(&mut self) -> &mut [[u8; 4]] {
-        let frame = &mut self.framebuffer.frame;
+    /// Handles events available on the descriptor returned by `event_fd`.
+    fn handle_events(&mut self) -> Result<()> {
+        Ok(())
+    }
 
-        // SAFETY: Frame length is divisible by 4 because of precondition in constructor.
-        // Then frame length is 4 * new_len, so its safe to cast it as new_len chunks of 4.
-        unsafe {
-            let new_len = frame.len() / 4;
-            slice::from_raw_parts_mut(frame.as_mut_ptr().cast(), new_len)
-        }
+    fn size(&self) -> Dimensions<PixelsUnit> {
+        self.back_buffer().size()
     }
 
-    pub fn size(&self) -> Dimensions<PixelsUnit> {
-        let width = self.framebuffer.var_screen_info.xres;
-        let height = self.framebuffer.var_screen_info.yres;
-        Dimensions::new(width, height)
+    fn pixel_mut(&mut self, pixel: Point<PixelsUnit>) -> DisplayPixel<'_> {
+        self.back_buffer_mut().pixel_mut(pixel)
     }
 
-    pub fn device_fd(&self) -> RawFd {
-        self.framebuffer.device.as_raw_fd()
+    /// Moves rows of pixels in the range up by the given distance, or down if it is negative. Rows
+    /// exposed at the other end are left unchanged.
+    fn scroll(&mut self, rows: Range<u32>, distance: i32) {
+        self.back_buffer_mut().scroll(rows, distance);
     }
 }
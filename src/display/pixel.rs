@@ -1,18 +1,212 @@
+use anyhow::{bail, Result};
+use framebuffer::{Bitfield, VarScreeninfo};
+
 use crate::color::Rgb;
 
+/// Layout of a pixel in the framebuffer memory, given by positions of the color channels in the
+/// little endian pixel value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PixelFormat {
+    bytes_per_pixel: usize,
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+    alpha: Option<Channel>,
+}
+
+impl PixelFormat {
+    pub fn from_screen_info(screen_info: &VarScreeninfo) -> Result<Self> {
+        let bits_per_pixel = screen_info.bits_per_pixel;
+        if !matches!(bits_per_pixel, 16 | 24 | 32) {
+            bail!(
+                "Unsupported framebuffer depth: {} bits per pixel",
+                bits_per_pixel
+            );
+        }
+        if screen_info.grayscale != 0 {
+            bail!("Grayscale and FOURCC framebuffers are not supported");
+        }
+
+        let channel = |bitfield| Channel::from_bitfield(bitfield, bits_per_pixel);
+        let alpha = if screen_info.transp.length > 0 {
+            Some(channel(&screen_info.transp)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            bytes_per_pixel: bits_per_pixel as usize / 8,
+            red: channel(&screen_info.red)?,
+            green: channel(&screen_info.green)?,
+            blue: channel(&screen_info.blue)?,
+            alpha,
+        })
+    }
+
+    /// Returns the 32 bit format with unused top byte, which all KMS drivers support.
+    pub fn xrgb8888() -> Self {
+        let channel = |offset| Channel { offset, length: 8 };
+        Self {
+            bytes_per_pixel: 4,
+            red: channel(16),
+            green: channel(8),
+            blue: channel(0),
+            alpha: None,
+        }
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bytes_per_pixel
+    }
+
+    /// Returns color of the pixel stored in the given bytes.
+    pub fn decode(&self, bytes: &[u8]) -> Rgb {
+        let mut value = [0; 4];
+        value[..bytes.len()].copy_from_slice(bytes);
+        let value = u32::from_le_bytes(value);
+        Rgb::new(
+            self.red.decode(value),
+            self.green.decode(value),
+            self.blue.decode(value),
+        )
+    }
+
+    fn encode(&self, rgb: Rgb) -> u32 {
+        // Display is not translucent, so alpha is always opaque.
+        let alpha = self.alpha.map_or(0, |alpha| alpha.encode(u8::MAX));
+        self.red.encode(rgb.red())
+            | self.green.encode(rgb.green())
+            | self.blue.encode(rgb.blue())
+            | alpha
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Channel {
+    offset: u32,
+    length: u32,
+}
+
+impl Channel {
+    fn from_bitfield(bitfield: &Bitfield, bits_per_pixel: u32) -> Result<Self> {
+        if bitfield.length == 0 || bitfield.length > 8 {
+            bail!("Unsupported channel length: {} bits", bitfield.length);
+        }
+        if bitfield.offset + bitfield.length > bits_per_pixel {
+            bail!(
+                "Channel at {} bits exceeds pixel of {} bits",
+                bitfield.offset,
+                bits_per_pixel
+            );
+        }
+        if bitfield.msb_right != 0 {
+            bail!("Channels with the most significant bit on the right are not supported");
+        }
+
+        Ok(Self {
+            offset: bitfield.offset,
+            length: bitfield.length,
+        })
+    }
+
+    fn encode(&self, value: u8) -> u32 {
+        (value as u32 >> (8 - self.length)) << self.offset
+    }
+
+    /// Returns value of the channel scaled to 8 bits, repeating its top bits in the missing low
+    /// ones, so the full intensity stays full.
+    fn decode(&self, pixel: u32) -> u8 {
+        let value = (pixel >> self.offset) & ((1 << self.length) - 1);
+        let mut scaled = value << (8 - self.length);
+        let mut length = self.length;
+        while length < 8 {
+            scaled |= scaled >> length;
+            length *= 2;
+        }
+        scaled as u8
+    }
+}
+
 #[derive(Debug)]
-pub struct DisplayPixel<'a>(&'a mut [u8; 4]);
+pub struct DisplayPixel<'a> {
+    bytes: &'a mut [u8],
+    format: PixelFormat,
+}
 
 impl<'a> DisplayPixel<'a> {
-    pub(super) fn from_frame_chunk(chunk: &'a mut [u8; 4]) -> Self {
-        Self(chunk)
+    pub(super) fn new(bytes: &'a mut [u8], format: PixelFormat) -> Self {
+        debug_assert_eq!(format.bytes_per_pixel, bytes.len());
+        Self { bytes, format }
     }
 
     pub fn set_rgb(&mut self, rgb: Rgb) {
-        // Layout is guaranteed by precondition in Display, which is the only place
-        // where this struct can be constructed.
-        self.0[0] = rgb.blue();
-        self.0[1] = rgb.green();
-        self.0[2] = rgb.red();
+        let value = self.format.encode(rgb).to_le_bytes();
+        self.bytes.copy_from_slice(&value[..self.bytes.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen_info(bits_per_pixel: u32, channels: [(u32, u32); 4]) -> VarScreeninfo {
+        let bitfield = |(offset, length)| Bitfield {
+            offset,
+            length,
+            msb_right: 0,
+        };
+        VarScreeninfo {
+            bits_per_pixel,
+            red: bitfield(channels[0]),
+            green: bitfield(channels[1]),
+            blue: bitfield(channels[2]),
+            transp: bitfield(channels[3]),
+            ..VarScreeninfo::default()
+        }
+    }
+
+    fn encode(screen_info: &VarScreeninfo, rgb: Rgb) -> Vec<u8> {
+        let format = PixelFormat::from_screen_info(screen_info).unwrap();
+        let mut bytes = vec![0; format.bytes_per_pixel()];
+        DisplayPixel::new(&mut bytes, format).set_rgb(rgb);
+        bytes
+    }
+
+    #[test]
+    fn test_set_rgb() {
+        let rgb = Rgb::new(0xff, 0x80, 0x10);
+        let bgrx = screen_info(32, [(16, 8), (8, 8), (0, 8), (0, 0)]);
+        assert_eq!(vec![0x10, 0x80, 0xff, 0x00], encode(&bgrx, rgb));
+        let xbgr = screen_info(32, [(0, 8), (8, 8), (16, 8), (0, 0)]);
+        assert_eq!(vec![0xff, 0x80, 0x10, 0x00], encode(&xbgr, rgb));
+        let argb = screen_info(32, [(16, 8), (8, 8), (0, 8), (24, 8)]);
+        assert_eq!(vec![0x10, 0x80, 0xff, 0xff], encode(&argb, rgb));
+        let rgb888 = screen_info(24, [(16, 8), (8, 8), (0, 8), (0, 0)]);
+        assert_eq!(vec![0x10, 0x80, 0xff], encode(&rgb888, rgb));
+        // Red 11111, green 100000, blue 00010.
+        let rgb565 = screen_info(16, [(11, 5), (5, 6), (0, 5), (0, 0)]);
+        assert_eq!(vec![0x02, 0xfc], encode(&rgb565, rgb));
+    }
+
+    #[test]
+    fn test_decode() {
+        let rgb = Rgb::new(0xff, 0x80, 0x10);
+        let bgrx = screen_info(32, [(16, 8), (8, 8), (0, 8), (0, 0)]);
+        let format = PixelFormat::from_screen_info(&bgrx).unwrap();
+        assert_eq!(rgb, format.decode(&encode(&bgrx, rgb)));
+        // Lost low bits are filled with the high ones.
+        let rgb565 = screen_info(16, [(11, 5), (5, 6), (0, 5), (0, 0)]);
+        let format = PixelFormat::from_screen_info(&rgb565).unwrap();
+        assert_eq!(
+            Rgb::new(0xff, 0x82, 0x10),
+            format.decode(&encode(&rgb565, rgb))
+        );
+    }
+
+    #[test]
+    fn test_unsupported_format() {
+        let rgb332 = screen_info(8, [(5, 3), (2, 3), (0, 2), (0, 0)]);
+        assert!(PixelFormat::from_screen_info(&rgb332).is_err());
+        let overflowing = screen_info(16, [(11, 8), (5, 6), (0, 5), (0, 0)]);
+        assert!(PixelFormat::from_screen_info(&overflowing).is_err());
     }
 }
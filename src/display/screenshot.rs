@@ -0,0 +1,146 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+
+use crate::display::back_buffer::BackBuffer;
+use crate::output;
+use crate::spatial::point::Point;
+
+/// File format of saved screenshots.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScreenshotFormat {
+    Png,
+    /// Binary portable pixmap, which is trivial to produce and read by other tools.
+    Ppm,
+}
+
+impl ScreenshotFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Ppm => "ppm",
+        }
+    }
+}
+
+impl FromStr for ScreenshotFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format {
+            "png" => Ok(ScreenshotFormat::Png),
+            "ppm" => Ok(ScreenshotFormat::Ppm),
+            _ => bail!("Unknown screenshot format: {}", format),
+        }
+    }
+}
+
+/// Where, how and when screenshots are saved.
+#[derive(Debug, Clone)]
+pub struct ScreenshotSettings {
+    pub directory: PathBuf,
+    pub format: ScreenshotFormat,
+    /// Whether a screenshot is saved once the shell exits, capturing its final output.
+    pub on_exit: bool,
+}
+
+impl ScreenshotSettings {
+    /// Saves pixels of the back buffer to a new file in the directory, named after the current
+    /// time. Returns path of the file.
+    pub fn save(&self, back_buffer: &BackBuffer) -> Result<PathBuf> {
+        let (file, path) =
+            output::create_timestamped_file(&self.directory, self.format.extension())?;
+        self.save_to(back_buffer, file)?;
+        Ok(path)
+    }
+
+    fn save_to(&self, back_buffer: &BackBuffer, file: File) -> Result<()> {
+        let mut writer = BufWriter::new(file);
+        match self.format {
+            ScreenshotFormat::Png => write_png(back_buffer, &mut writer)?,
+            ScreenshotFormat::Ppm => write_ppm(back_buffer, &mut writer)?,
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn write_png(back_buffer: &BackBuffer, writer: impl Write) -> Result<()> {
+    let size = back_buffer.size();
+    let mut encoder = png::Encoder::new(writer, size.width(), size.height());
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&rgb_bytes(back_buffer))?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_ppm(back_buffer: &BackBuffer, mut writer: impl Write) -> Result<()> {
+    let size = back_buffer.size();
+    write!(writer, "P6\n{} {}\n255\n", size.width(), size.height())?;
+    writer.write_all(&rgb_bytes(back_buffer))?;
+    Ok(())
+}
+
+/// Returns pixels converted from the display format to RGB with 8 bits per channel, row by row.
+fn rgb_bytes(back_buffer: &BackBuffer) -> Vec<u8> {
+    let size = back_buffer.size();
+    let mut bytes = Vec::with_capacity(size.width() as usize * size.height() as usize * 3);
+    for vertical_distance in 0..size.height() {
+        for horizontal_distance in 0..size.width() {
+            let rgb = back_buffer.rgb(Point::new(horizontal_distance, vertical_distance));
+            bytes.extend_from_slice(&[rgb.red(), rgb.green(), rgb.blue()]);
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Rgb;
+    use crate::display::pixel::PixelFormat;
+    use crate::spatial::dimension::Dimensions;
+
+    fn back_buffer() -> BackBuffer {
+        let mut back_buffer = BackBuffer::new(Dimensions::new(2, 1), PixelFormat::xrgb8888());
+        back_buffer
+            .pixel_mut(Point::new(0, 0))
+            .set_rgb(Rgb::new(1, 2, 3));
+        back_buffer
+            .pixel_mut(Point::new(1, 0))
+            .set_rgb(Rgb::new(4, 5, 6));
+        back_buffer
+    }
+
+    #[test]
+    fn test_write_ppm() {
+        let mut bytes = Vec::new();
+        write_ppm(&back_buffer(), &mut bytes).unwrap();
+        assert_eq!(b"P6\n2 1\n255\n\x01\x02\x03\x04\x05\x06".as_slice(), bytes);
+    }
+
+    #[test]
+    fn test_write_png() {
+        let mut bytes = Vec::new();
+        write_png(&back_buffer(), &mut bytes).unwrap();
+
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut image = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut image).unwrap();
+        assert_eq!((2, 1), (info.width, info.height));
+        assert_eq!(png::ColorType::Rgb, info.color_type);
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], image);
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(ScreenshotFormat::Png, "png".parse().unwrap());
+        assert_eq!(ScreenshotFormat::Ppm, "ppm".parse().unwrap());
+        assert!("jpg".parse::<ScreenshotFormat>().is_err());
+    }
+}
@@ -1,9 +1,10 @@
-use std::fs;
 use std::num::NonZeroUsize;
+use std::{fs, iter};
 
 use anyhow::{anyhow, Result};
 use fontdue::{Font, FontSettings, Metrics};
 use lru::LruCache;
+use unicode_normalization::char::compose;
 
 use crate::font::raster_iterator::RasterIterator;
 use crate::spatial::dimension::Dimensions;
@@ -11,8 +12,8 @@ use crate::spatial::PixelsUnit;
 
 pub mod raster_iterator;
 
-// SAFETY: 256 is not equal to 0
-const CACHE_CAPACITY: NonZeroUsize = unsafe { NonZeroUsize::new_unchecked(256) };
+const CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(256).unwrap();
+const CLUSTER_CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(64).unwrap();
 
 #[derive(Debug)]
 pub struct FontRenderer {
@@ -20,14 +21,20 @@ pub struct FontRenderer {
     font: Font,
     ascent: i32,
     cache: LruCache<char, (Metrics, Vec<u8>)>,
+    cluster_cache: LruCache<Box<[char]>, (Metrics, Vec<u8>)>,
 }
 
 impl FontRenderer {
     pub fn new(size: u32, font_path: &str) -> Result<Self> {
+        let font_data = fs::read(font_path)?;
+        Self::from_bytes(size, font_data)
+    }
+
+    /// Creates renderer of the font loaded from the contents of its file.
+    pub fn from_bytes(size: u32, font_data: Vec<u8>) -> Result<Self> {
         let size = size as f32;
 
         let font_settings = FontSettings::default();
-        let font_data = fs::read(font_path)?;
         let font = Font::from_bytes(font_data, font_settings).map_err(|error| anyhow!(error))?;
 
         let line_metrics = font
@@ -36,23 +43,136 @@ impl FontRenderer {
         let ascent = line_metrics.ascent.ceil() as i32;
 
         let cache = LruCache::new(CACHE_CAPACITY);
+        let cluster_cache = LruCache::new(CLUSTER_CACHE_CAPACITY);
 
         Ok(Self {
             size,
             font,
             ascent,
             cache,
+            cluster_cache,
         })
     }
 
-    pub fn create_raster(&mut self, character: char) -> RasterIterator {
+    pub fn create_raster(&mut self, character: char) -> RasterIterator<'_> {
         let rasterize = || self.font.rasterize(character, self.size);
         let (metrics, raster) = self.cache.get_or_insert(character, rasterize);
         RasterIterator::new(*metrics, raster, self.ascent)
     }
 
+    /// Creates raster of the character with the combining marks drawn over it. Marks are composed
+    /// with the character into a single glyph, if the font has a precomposed one.
+    pub fn create_cluster_raster(&mut self, character: char, marks: &[char]) -> RasterIterator<'_> {
+        if marks.is_empty() {
+            return self.create_raster(character);
+        }
+
+        let cluster = iter::once(character).chain(marks.iter().copied()).collect();
+        let rasterize = || rasterize_cluster(&self.font, self.size, character, marks);
+        let (metrics, raster) = self.cluster_cache.get_or_insert(cluster, rasterize);
+        RasterIterator::new(*metrics, raster, self.ascent)
+    }
+
+    pub fn ascent(&self) -> u32 {
+        self.ascent as u32
+    }
+
     pub fn character_size(&self, character: char) -> Dimensions<PixelsUnit> {
         let metrics = self.font.metrics(character, self.size);
         Dimensions::new(metrics.width as u32, metrics.height as u32)
     }
 }
+
+fn rasterize_cluster(
+    font: &Font,
+    size: f32,
+    character: char,
+    marks: &[char],
+) -> (Metrics, Vec<u8>) {
+    let has_glyph = |character| font.lookup_glyph_index(character) != 0;
+    let mut base = character;
+    let mut remaining_marks = Vec::new();
+    for &mark in marks {
+        match compose(base, mark) {
+            Some(composed) if remaining_marks.is_empty() && has_glyph(composed) => base = composed,
+            _ => remaining_marks.push(mark),
+        }
+    }
+
+    // Marks missing in the font, like the zero width joiner, are skipped instead of being drawn as
+    // the replacement glyph.
+    let remaining_marks = remaining_marks.into_iter().filter(|&mark| has_glyph(mark));
+    let glyphs: Vec<_> = iter::once(base)
+        .chain(remaining_marks)
+        .map(|character| font.rasterize(character, size))
+        .collect();
+    merge_glyphs(&glyphs)
+}
+
+/// Draws the glyphs over each other into a single raster spanning all of them.
+fn merge_glyphs(glyphs: &[(Metrics, Vec<u8>)]) -> (Metrics, Vec<u8>) {
+    // Distance of the top of the glyph from the baseline, the same as in the raster iterator.
+    let top = |metrics: &Metrics| (-metrics.bounds.height - metrics.bounds.ymin).floor() as i32;
+    let left = glyphs.iter().map(|(metrics, _)| metrics.xmin).min();
+    let right = glyphs
+        .iter()
+        .map(|(metrics, _)| metrics.xmin + metrics.width as i32)
+        .max();
+    let upper = glyphs.iter().map(|(metrics, _)| top(metrics)).min();
+    let lower = glyphs
+        .iter()
+        .map(|(metrics, _)| top(metrics) + metrics.height as i32)
+        .max();
+    let (Some(left), Some(right), Some(upper), Some(lower)) = (left, right, upper, lower) else {
+        return (Metrics::default(), Vec::new());
+    };
+
+    let width = (right - left) as usize;
+    let height = (lower - upper) as usize;
+    let mut raster = vec![0; width * height];
+    for (metrics, glyph_raster) in glyphs {
+        for (index, &alpha) in glyph_raster.iter().enumerate() {
+            let horizontal_distance = (metrics.xmin - left) as usize + index % metrics.width;
+            let vertical_distance = (top(metrics) - upper) as usize + index / metrics.width;
+            let pixel = &mut raster[vertical_distance * width + horizontal_distance];
+            *pixel = (*pixel).max(alpha);
+        }
+    }
+
+    let mut metrics = glyphs[0].0;
+    metrics.xmin = left;
+    metrics.width = width;
+    metrics.height = height;
+    metrics.bounds.height = height as f32;
+    metrics.bounds.ymin = -(upper as f32) - height as f32;
+    (metrics, raster)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(xmin: i32, top: i32, width: usize, raster: Vec<u8>) -> (Metrics, Vec<u8>) {
+        let mut metrics = Metrics {
+            xmin,
+            width,
+            height: raster.len() / width,
+            ..Metrics::default()
+        };
+        metrics.bounds.height = metrics.height as f32;
+        metrics.bounds.ymin = -(top as f32) - metrics.bounds.height;
+        (metrics, raster)
+    }
+
+    #[test]
+    fn test_merge_glyphs() {
+        let base = glyph(1, 2, 2, vec![10, 20, 30, 40]);
+        let mark = glyph(2, 1, 2, vec![50, 60, 5, 5]);
+        let (metrics, raster) = merge_glyphs(&[base, mark]);
+        assert_eq!((1, 3, 3), (metrics.xmin, metrics.width, metrics.height));
+        assert_eq!(vec![0, 50, 60, 10, 20, 5, 30, 40, 0], raster);
+        let rows =
+            RasterIterator::new(metrics, &raster, 0).map(|(point, _)| point.vertical_distance());
+        assert_eq!(Some(1), rows.min());
+    }
+}
@@ -14,7 +14,7 @@ pub struct RasterIterator<'a> {
 }
 
 impl<'a> RasterIterator<'a> {
-    pub fn new(metrics: Metrics, raster: &'a Vec<u8>, ascent: i32) -> Self {
+    pub fn new(metrics: Metrics, raster: &'a [u8], ascent: i32) -> Self {
         debug_assert_eq!(raster.len(), metrics.width * metrics.height);
 
         let raster_iterator = raster.iter().enumerate();
@@ -30,22 +30,25 @@ impl<'a> Iterator for RasterIterator<'a> {
     type Item = (Point<PixelsUnit>, Alpha);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (index, alpha) = self.raster_iterator.next()?;
-
-        let horizontal_distance = self.metrics.xmin + (index % self.metrics.width) as i32;
-        debug_assert!(horizontal_distance >= 0);
-
-        let shift = (-self.metrics.bounds.height - self.metrics.bounds.ymin).floor() as i32;
-        let vertical_distance = shift + self.ascent + (index / self.metrics.width) as i32;
-        debug_assert!(vertical_distance >= 0);
-
-        let point = Point::new(horizontal_distance as u32, vertical_distance as u32);
-        let alpha = Alpha::new(*alpha);
-
-        Some((point, alpha))
+        loop {
+            let (index, alpha) = self.raster_iterator.next()?;
+
+            let horizontal_distance = self.metrics.xmin + (index % self.metrics.width) as i32;
+            let shift = (-self.metrics.bounds.height - self.metrics.bounds.ymin).floor() as i32;
+            let vertical_distance = shift + self.ascent + (index / self.metrics.width) as i32;
+            // Glyphs taller than the ascent, like capitals with stacked accents, reach above the
+            // cell, where they cannot be drawn.
+            if horizontal_distance < 0 || vertical_distance < 0 {
+                continue;
+            }
+
+            let point = Point::new(horizontal_distance as u32, vertical_distance as u32);
+            let alpha = Alpha::new(*alpha);
+            return Some((point, alpha));
+        }
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.raster_iterator.size_hint()
+        (0, self.raster_iterator.size_hint().1)
     }
 }
@@ -0,0 +1,84 @@
+/// Keys handled by the terminal itself instead of being forwarded to the shell.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TerminalKey {
+    Viewport(ViewportKey),
+    Screenshot,
+    Dump,
+}
+
+/// Keys moving the viewport through the scrollback.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ViewportKey {
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+}
+
+impl TerminalKey {
+    /// Sequences sent by Shift with Home, PageUp, PageDown, End, F12 and F11 in xterm. The default
+    /// keymap of the Linux console does not send them, so they are followed by sequences of F15 to
+    /// F20, which it sends for Shift with F5 to F10. The keymap in the README makes the console
+    /// send the former ones too.
+    const SEQUENCES: [(&'static [u8], TerminalKey); 12] = [
+        (b"\x1b[1;2H", TerminalKey::Viewport(ViewportKey::Top)),
+        (b"\x1b[5;2~", TerminalKey::Viewport(ViewportKey::PageUp)),
+        (b"\x1b[6;2~", TerminalKey::Viewport(ViewportKey::PageDown)),
+        (b"\x1b[1;2F", TerminalKey::Viewport(ViewportKey::Bottom)),
+        (b"\x1b[24;2~", TerminalKey::Screenshot),
+        (b"\x1b[23;2~", TerminalKey::Dump),
+        (b"\x1b[28~", TerminalKey::Viewport(ViewportKey::Top)),
+        (b"\x1b[29~", TerminalKey::Viewport(ViewportKey::PageUp)),
+        (b"\x1b[31~", TerminalKey::Viewport(ViewportKey::PageDown)),
+        (b"\x1b[32~", TerminalKey::Viewport(ViewportKey::Bottom)),
+        (b"\x1b[34~", TerminalKey::Screenshot),
+        (b"\x1b[33~", TerminalKey::Dump),
+    ];
+
+    /// Returns key at the beginning of bytes together with length of its sequence.
+    pub fn parse(bytes: &[u8]) -> Option<(Self, usize)> {
+        Self::SEQUENCES
+            .iter()
+            .find(|(sequence, _)| bytes.starts_with(sequence))
+            .map(|(sequence, key)| (*key, sequence.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Some((TerminalKey::Viewport(ViewportKey::PageUp), 6)),
+            TerminalKey::parse(b"\x1b[5;2~\x1b[5;2~")
+        );
+        assert_eq!(
+            Some((TerminalKey::Viewport(ViewportKey::Bottom), 6)),
+            TerminalKey::parse(b"\x1b[1;2F")
+        );
+        assert_eq!(
+            Some((TerminalKey::Viewport(ViewportKey::PageUp), 5)),
+            TerminalKey::parse(b"\x1b[29~\x1b[29~")
+        );
+        assert_eq!(
+            Some((TerminalKey::Screenshot, 7)),
+            TerminalKey::parse(b"\x1b[24;2~")
+        );
+        assert_eq!(
+            Some((TerminalKey::Screenshot, 5)),
+            TerminalKey::parse(b"\x1b[34~")
+        );
+        assert_eq!(
+            Some((TerminalKey::Dump, 7)),
+            TerminalKey::parse(b"\x1b[23;2~")
+        );
+        assert_eq!(
+            Some((TerminalKey::Dump, 5)),
+            TerminalKey::parse(b"\x1b[33~")
+        );
+        assert_eq!(None, TerminalKey::parse(b"\x1b[5~"));
+        assert_eq!(None, TerminalKey::parse(b"a\x1b[28~"));
+    }
+}
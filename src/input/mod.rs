@@ -6,6 +6,8 @@ use nix::libc;
 use nix::sys::termios;
 use nix::sys::termios::{SetArg, Termios};
 
+pub mod key;
+
 #[derive(Debug)]
 pub struct InputTerminal {
     old_terminal_attributes: Termios,
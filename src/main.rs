@@ -5,9 +5,13 @@ use anyhow::Result;
 use simplelog::{ConfigBuilder, LevelFilter, WriteLogger};
 
 use crate::args::Args;
+use crate::display::fbdev::FramebufferDisplay;
+use crate::display::kms::KmsDisplay;
+use crate::display::screenshot::ScreenshotSettings;
 use crate::display::Display;
 use crate::font::FontRenderer;
 use crate::input::InputTerminal;
+use crate::terminal::cells::dump::DumpSettings;
 use crate::terminal::Terminal;
 
 mod args;
@@ -15,6 +19,7 @@ mod color;
 mod display;
 mod font;
 mod input;
+mod output;
 mod spatial;
 mod terminal;
 
@@ -41,10 +46,34 @@ fn start() -> Result<()> {
     log::debug!("Command line arguments parsed: {:?}", args);
 
     let input = InputTerminal::initialize()?;
-    let display = Display::new(&args.framebuffer_device_path)?;
+    let display: Box<dyn Display> = match args.kms_device_path {
+        Some(ref path) => Box::new(KmsDisplay::new(path)?),
+        None => Box::new(FramebufferDisplay::new(
+            &args.framebuffer_device_path,
+            args.page_flipping,
+        )?),
+    };
     let font = FontRenderer::new(args.font_size_px, &args.font_path)?;
-    let terminal = Terminal::new(input, display, font, &args.shell_path)?;
-    terminal.run()?;
-
-    Ok(())
+    let screenshot_settings = ScreenshotSettings {
+        directory: args.screenshot_directory,
+        format: args.screenshot_format,
+        on_exit: args.screenshot_on_exit,
+    };
+    let dump_settings = DumpSettings {
+        directory: args.dump_directory,
+        format: args.dump_format,
+        scrollback: args.dump_scrollback,
+        requests: args.dump_requests,
+    };
+    let terminal = Terminal::new(
+        display,
+        font,
+        &args.shell_path,
+        args.scrollback_lines,
+        screenshot_settings,
+        dump_settings,
+    )?;
+    let result = terminal.run();
+    input.finish()?;
+    result
 }
@@ -0,0 +1,50 @@
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use time::macros::format_description;
+use time::OffsetDateTime;
+
+/// Creates a new file in the directory, named after the current time, so files saved one after
+/// another sort in order. Files saved within the same millisecond get a numeric suffix, so none
+/// is overwritten. Returns the file together with its path.
+pub fn create_timestamped_file(directory: &Path, extension: &str) -> Result<(File, PathBuf)> {
+    let time = OffsetDateTime::now_utc().format(format_description!(
+        "[year][month][day]-[hour][minute][second].[subsecond digits:3]"
+    ))?;
+    let mut suffix = 0;
+    loop {
+        let name = if suffix == 0 {
+            format!("ft-{}.{}", time, extension)
+        } else {
+            format!("ft-{}-{}.{}", time, suffix, extension)
+        };
+        let path = directory.join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => suffix += 1,
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn test_create_timestamped_file() {
+        let directory = env::temp_dir().join(format!("ft-test-output-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let paths: Vec<_> = (0..3)
+            .map(|_| create_timestamped_file(&directory, "txt").unwrap().1)
+            .collect();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(paths[0] != paths[1] && paths[1] != paths[2] && paths[0] != paths[2]);
+        assert!(paths.iter().all(|path| path.starts_with(&directory)));
+    }
+}
@@ -6,7 +6,7 @@ use nix::pty::Winsize;
 use crate::spatial::point::Point;
 use crate::spatial::{CellsUnit, PixelsUnit};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Dimensions<Unit> {
     width: u32,
     height: u32,
@@ -28,7 +28,7 @@ impl<Unit> Dimensions<Unit> {
     }
 
     pub fn contains(&self, point: Point<Unit>) -> bool {
-        point.horizontal_distance() <= self.width && point.vertical_distance() <= self.height
+        point.horizontal_distance() < self.width && point.vertical_distance() < self.height
     }
 
     pub fn width(&self) -> u32 {
@@ -58,3 +58,17 @@ impl From<Dimensions<CellsUnit>> for Winsize {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        let size = Dimensions::<PixelsUnit>::new(8, 16);
+        assert!(size.contains(Point::new(0, 0)));
+        assert!(size.contains(Point::new(7, 15)));
+        assert!(!size.contains(Point::new(8, 15)));
+        assert!(!size.contains(Point::new(7, 16)));
+    }
+}
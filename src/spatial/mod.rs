@@ -1,8 +1,9 @@
 pub mod dimension;
 pub mod point;
+pub mod rectangle;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct PixelsUnit;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct CellsUnit;
@@ -4,7 +4,7 @@ use std::ops::Add;
 use crate::spatial::dimension::Dimensions;
 use crate::spatial::{CellsUnit, PixelsUnit};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Point<Unit> {
     horizontal_distance: u32,
     vertical_distance: u32,
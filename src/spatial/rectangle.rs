@@ -0,0 +1,55 @@
+use crate::spatial::dimension::Dimensions;
+use crate::spatial::point::Point;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rectangle<Unit> {
+    origin: Point<Unit>,
+    size: Dimensions<Unit>,
+}
+
+impl<Unit: Copy> Rectangle<Unit> {
+    pub fn new(origin: Point<Unit>, size: Dimensions<Unit>) -> Self {
+        Self { origin, size }
+    }
+
+    pub fn origin(&self) -> Point<Unit> {
+        self.origin
+    }
+
+    pub fn size(&self) -> Dimensions<Unit> {
+        self.size
+    }
+
+    /// Returns the rectangle extended by the other one, if they have the same vertical span and
+    /// the other one starts where this one ends.
+    pub fn joined(&self, other: Rectangle<Unit>) -> Option<Rectangle<Unit>> {
+        let end = self.origin.horizontal_distance() + self.size.width();
+        let adjacent = self.origin.vertical_distance() == other.origin.vertical_distance()
+            && self.size.height() == other.size.height()
+            && end == other.origin.horizontal_distance();
+        if !adjacent {
+            return None;
+        }
+
+        let width = self.size.width() + other.size.width();
+        let size = Dimensions::new(width, self.size.height());
+        Some(Rectangle::new(self.origin, size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::PixelsUnit;
+
+    #[test]
+    fn test_joined() {
+        let size = Dimensions::<PixelsUnit>::new(8, 16);
+        let first = Rectangle::new(Point::new(0, 16), size);
+        let second = Rectangle::new(Point::new(8, 16), size);
+        let joined = Rectangle::new(Point::new(0, 16), Dimensions::new(16, 16));
+        assert_eq!(Some(joined), first.joined(second));
+        assert_eq!(None, second.joined(first));
+        assert_eq!(None, first.joined(Rectangle::new(Point::new(8, 32), size)));
+    }
+}
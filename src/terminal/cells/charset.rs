@@ -0,0 +1,107 @@
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Charset {
+    Ascii,
+    /// DEC Special Graphics, which replaces lowercase letters with line drawing characters.
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    pub fn translate(self, character: char) -> char {
+        match self {
+            Charset::Ascii => character,
+            Charset::DecSpecialGraphics => match character {
+                '_' => ' ',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                _ => character,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CharsetSlot {
+    G0,
+    G1,
+}
+
+/// Designated G0 and G1 charsets with the one currently shifted in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Charsets {
+    g0: Charset,
+    g1: Charset,
+    active: CharsetSlot,
+}
+
+impl Charsets {
+    pub const fn new() -> Self {
+        Self {
+            g0: Charset::Ascii,
+            g1: Charset::Ascii,
+            active: CharsetSlot::G0,
+        }
+    }
+
+    pub fn designate(&mut self, slot: CharsetSlot, charset: Charset) {
+        match slot {
+            CharsetSlot::G0 => self.g0 = charset,
+            CharsetSlot::G1 => self.g1 = charset,
+        }
+    }
+
+    pub fn shift(&mut self, slot: CharsetSlot) {
+        self.active = slot;
+    }
+
+    pub fn translate(&self, character: char) -> char {
+        let charset = match self.active {
+            CharsetSlot::G0 => self.g0,
+            CharsetSlot::G1 => self.g1,
+        };
+        charset.translate(character)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        let mut charsets = Charsets::new();
+        charsets.designate(CharsetSlot::G1, Charset::DecSpecialGraphics);
+        assert_eq!('q', charsets.translate('q'));
+        charsets.shift(CharsetSlot::G1);
+        assert_eq!('─', charsets.translate('q'));
+        assert_eq!('Q', charsets.translate('Q'));
+    }
+}
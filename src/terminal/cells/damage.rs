@@ -0,0 +1,240 @@
+use std::mem;
+use std::ops::Range;
+
+use crate::spatial::dimension::Dimensions;
+use crate::spatial::point::Point;
+use crate::spatial::CellsUnit;
+
+/// Cells of the viewport changed since the last render, tracked as a range of columns per line,
+/// together with scrolls of the lines, which are rendered by moving the already drawn content.
+#[derive(Debug)]
+pub struct Damage {
+    lines: Vec<Option<Range<u32>>>,
+    scrolls: Vec<RendererAction>,
+    width: u32,
+    /// Cursor cell at the last render, which has to be repainted once the cursor leaves it.
+    cursor: Option<Point<CellsUnit>>,
+}
+
+impl Damage {
+    pub fn new(size: Dimensions<CellsUnit>) -> Self {
+        Self {
+            lines: vec![None; size.height() as usize],
+            scrolls: Vec::new(),
+            width: size.width(),
+            cursor: None,
+        }
+    }
+
+    /// Changes size of the tracked viewport, damaging all of it.
+    pub fn resize(&mut self, size: Dimensions<CellsUnit>) {
+        *self = Self::new(size);
+        self.damage_all();
+    }
+
+    pub fn damage_cells(&mut self, line: u32, cells: Range<u32>) {
+        let end = cells.end.min(self.width);
+        let start = cells.start.min(end);
+        let Some(damage) = self.lines.get_mut(line as usize) else {
+            return;
+        };
+        if start == end {
+            return;
+        }
+
+        *damage = match damage.take() {
+            Some(damage) => Some(damage.start.min(start)..damage.end.max(end)),
+            None => Some(start..end),
+        };
+    }
+
+    pub fn damage_lines(&mut self, lines: Range<u32>) {
+        for line in lines {
+            self.damage_cells(line, 0..self.width);
+        }
+    }
+
+    pub fn damage_all(&mut self) {
+        // Everything is rendered again, so moving the content beforehand would be wasted.
+        self.scrolls.clear();
+        self.damage_lines(0..self.lines.len() as u32);
+    }
+
+    /// Moves lines of the region up by the given count, or down if it is negative. Damage of the
+    /// moved lines moves with them and lines exposed at the other end are damaged.
+    pub fn scroll(&mut self, region: Range<u32>, lines: i32) {
+        let region_length = region.len() as u32;
+        let count = lines.unsigned_abs();
+        if lines == 0 || region.end as usize > self.lines.len() {
+            return;
+        }
+        if count >= region_length {
+            self.damage_lines(region);
+            return;
+        }
+
+        let range = region.start as usize..region.end as usize;
+        let exposed = if lines > 0 {
+            self.lines[range].rotate_left(count as usize);
+            region.end - count..region.end
+        } else {
+            self.lines[range].rotate_right(count as usize);
+            region.start..region.start + count
+        };
+        for line in exposed.clone() {
+            self.lines[line as usize] = None;
+        }
+        self.damage_lines(exposed);
+
+        // Drawn cursor moves with the content, so it is erased at its new place.
+        if let Some(cursor) = self.cursor {
+            if region.contains(&cursor.vertical_distance()) {
+                let line = cursor.vertical_distance() as i64 - lines as i64;
+                self.cursor = (region.start as i64..region.end as i64)
+                    .contains(&line)
+                    .then(|| Point::new(cursor.horizontal_distance(), line as u32));
+            }
+        }
+
+        self.push_scroll(region, lines);
+    }
+
+    /// Adds the scroll, merging it with the previous one of the same region and direction.
+    fn push_scroll(&mut self, region: Range<u32>, lines: i32) {
+        if let Some(RendererAction::Scroll {
+            region: last_region,
+            lines: last_lines,
+        }) = self.scrolls.last_mut()
+        {
+            if *last_region == region && last_lines.signum() == lines.signum() {
+                *last_lines += lines;
+                // All lines of the region are damaged already, so moving them is pointless.
+                if last_lines.unsigned_abs() >= region.len() as u32 {
+                    self.scrolls.pop();
+                }
+                return;
+            }
+        }
+        self.scrolls.push(RendererAction::Scroll { region, lines });
+    }
+
+    /// Returns damaged cells and resets the damage. Cell left by the cursor since the last call is
+    /// damaged as well, so the cursor drawn over it is erased.
+    pub fn take(&mut self, cursor: Option<Point<CellsUnit>>) -> Vec<RendererAction> {
+        if self.cursor != cursor {
+            if let Some(old_cursor) = self.cursor {
+                let column = old_cursor.horizontal_distance();
+                self.damage_cells(old_cursor.vertical_distance(), column..column + 1);
+            }
+            self.cursor = cursor;
+        }
+
+        let height = self.lines.len();
+        let damaged_cells = mem::replace(&mut self.lines, vec![None; height])
+            .into_iter()
+            .enumerate()
+            .filter_map(|(line, cells)| {
+                cells.map(|cells| RendererAction::RenderCells {
+                    line: line as u32,
+                    cells,
+                })
+            });
+        // Scrolls go first, as the damage refers to lines at their final place.
+        mem::take(&mut self.scrolls)
+            .into_iter()
+            .chain(damaged_cells)
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RendererAction {
+    RenderCells {
+        line: u32,
+        cells: Range<u32>,
+    },
+    /// Moves lines of the region up by the given count, or down if it is negative.
+    Scroll {
+        region: Range<u32>,
+        lines: i32,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_damage() {
+        let mut damage = Damage::new(Dimensions::new(10, 3));
+        assert!(damage.take(Some(Point::new(2, 1))).is_empty());
+
+        damage.damage_cells(0, 3..5);
+        damage.damage_cells(0, 7..12);
+        damage.damage_cells(5, 0..1);
+        let actions = damage.take(Some(Point::new(4, 2)));
+        assert_eq!(
+            vec![
+                RendererAction::RenderCells {
+                    line: 0,
+                    cells: 3..10
+                },
+                RendererAction::RenderCells {
+                    line: 1,
+                    cells: 2..3
+                },
+            ],
+            actions
+        );
+        assert!(damage.take(Some(Point::new(4, 2))).is_empty());
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut damage = Damage::new(Dimensions::new(10, 4));
+        damage.take(Some(Point::new(0, 3)));
+        damage.damage_cells(2, 1..2);
+        damage.scroll(1..4, 1);
+        damage.scroll(1..4, 1);
+        let actions = damage.take(Some(Point::new(0, 3)));
+        assert_eq!(
+            vec![
+                RendererAction::Scroll {
+                    region: 1..4,
+                    lines: 2
+                },
+                // Cursor drawn at the last line was moved along with it.
+                RendererAction::RenderCells {
+                    line: 1,
+                    cells: 0..1
+                },
+                RendererAction::RenderCells {
+                    line: 2,
+                    cells: 0..10
+                },
+                RendererAction::RenderCells {
+                    line: 3,
+                    cells: 0..10
+                },
+            ],
+            actions
+        );
+
+        damage.damage_cells(3, 1..2);
+        damage.scroll(0..4, -1);
+        let actions = damage.take(Some(Point::new(0, 3)));
+        assert_eq!(
+            vec![
+                RendererAction::Scroll {
+                    region: 0..4,
+                    lines: -1
+                },
+                RendererAction::RenderCells {
+                    line: 0,
+                    cells: 0..10
+                },
+            ],
+            actions
+        );
+    }
+}
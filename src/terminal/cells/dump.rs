@@ -0,0 +1,412 @@
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::{io, mem};
+
+use anyhow::{bail, Result};
+
+use crate::color::{Alpha, Rgb};
+use crate::output;
+use crate::terminal::cells::line::{CellWidth, Line};
+use crate::terminal::cells::style::{Attributes, Color, Style};
+use crate::terminal::cells::Cells;
+
+/// Format of the text dump of cells.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// Text without any styles.
+    Plain,
+    /// Text styled with SGR escape sequences, which can be shown by `cat` or `less -R`.
+    Ansi,
+    /// Page with the text in a `pre` element, styled with inline styles.
+    Html,
+}
+
+impl DumpFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            DumpFormat::Plain => "txt",
+            DumpFormat::Ansi => "ans",
+            DumpFormat::Html => "html",
+        }
+    }
+}
+
+impl FromStr for DumpFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format {
+            "plain" => Ok(DumpFormat::Plain),
+            "ansi" => Ok(DumpFormat::Ansi),
+            "html" => Ok(DumpFormat::Html),
+            _ => bail!("Unknown dump format: {}", format),
+        }
+    }
+}
+
+/// Colors of cells in the HTML dump, which cannot refer to those of the terminal.
+#[derive(Debug, Copy, Clone)]
+pub struct DumpPalette {
+    /// Color of text with the default foreground.
+    pub foreground: Rgb,
+    /// Color of cells with the default background.
+    pub background: Rgb,
+    /// Opacity of faint text blended over its background.
+    pub faint_alpha: Alpha,
+}
+
+/// Where and how text dumps are saved.
+#[derive(Debug, Clone)]
+pub struct DumpSettings {
+    pub directory: PathBuf,
+    pub format: DumpFormat,
+    /// Whether the scrollback is dumped before the screen.
+    pub scrollback: bool,
+    /// Whether programs can request dumps with a control sequence.
+    pub requests: bool,
+}
+
+impl DumpSettings {
+    /// Saves dump of the cells in the given format to a new file in the directory, named after
+    /// the current time. Returns path of the file.
+    pub fn save(&self, cells: &Cells, format: DumpFormat, palette: DumpPalette) -> Result<PathBuf> {
+        let (mut file, path) =
+            output::create_timestamped_file(&self.directory, format.extension())?;
+        io::Write::write_all(
+            &mut file,
+            cells.dump(format, self.scrollback, palette).as_bytes(),
+        )?;
+        Ok(path)
+    }
+}
+
+/// Text of adjacent cells with the same style.
+#[derive(Debug, PartialEq, Eq)]
+struct Span {
+    style: Style,
+    text: String,
+}
+
+/// Serializes the lines in the given format. Lines wrapped at the right margin are joined,
+/// whitespace is trimmed from the end of each line and empty lines at the end are dropped.
+pub fn dump<'a>(
+    lines: impl IntoIterator<Item = &'a Line>,
+    format: DumpFormat,
+    palette: DumpPalette,
+) -> String {
+    let lines = join_wrapped_lines(lines, format != DumpFormat::Plain);
+    match format {
+        DumpFormat::Plain => dump_plain(&lines),
+        DumpFormat::Ansi => dump_ansi(&lines),
+        DumpFormat::Html => dump_html(&lines, palette),
+    }
+}
+
+fn join_wrapped_lines<'a>(
+    lines: impl IntoIterator<Item = &'a Line>,
+    keep_styled_whitespace: bool,
+) -> Vec<Vec<Span>> {
+    let mut joined_lines = Vec::new();
+    let mut spans: Vec<Span> = Vec::new();
+    for line in lines {
+        // Spacer is the second half of the double width character preceding it.
+        let cells = line.iter().filter(|cell| cell.width() != CellWidth::Spacer);
+        for cell in cells {
+            let mut text = String::from(cell.character().unwrap_or(' '));
+            text.extend(cell.marks());
+            match spans.last_mut() {
+                Some(span) if span.style == cell.style() => span.text.push_str(&text),
+                _ => spans.push(Span {
+                    style: cell.style(),
+                    text,
+                }),
+            }
+        }
+
+        if !line.is_wrapped() {
+            trim_end(&mut spans, keep_styled_whitespace);
+            joined_lines.push(mem::take(&mut spans));
+        }
+    }
+    if !spans.is_empty() {
+        trim_end(&mut spans, keep_styled_whitespace);
+        joined_lines.push(spans);
+    }
+
+    while joined_lines.last().is_some_and(Vec::is_empty) {
+        joined_lines.pop();
+    }
+    joined_lines
+}
+
+/// Removes whitespace from the end of the line. Whitespace with a background or decoration can be
+/// kept, as it is visible.
+fn trim_end(spans: &mut Vec<Span>, keep_styled_whitespace: bool) {
+    while let Some(span) = spans.last_mut() {
+        if keep_styled_whitespace && shows_whitespace(span.style) {
+            return;
+        }
+        let length = span.text.trim_end().len();
+        span.text.truncate(length);
+        if !span.text.is_empty() {
+            return;
+        }
+        spans.pop();
+    }
+}
+
+fn shows_whitespace(style: Style) -> bool {
+    let decorations = Attributes::ALL_UNDERLINES
+        .union(Attributes::INVERSE)
+        .union(Attributes::STRIKETHROUGH);
+    style.background() != Color::Default || style.attributes().intersects(decorations)
+}
+
+fn dump_plain(lines: &[Vec<Span>]) -> String {
+    let mut output = String::new();
+    for spans in lines {
+        for span in spans {
+            output.push_str(&span.text);
+        }
+        output.push('\n');
+    }
+    output
+}
+
+fn dump_ansi(lines: &[Vec<Span>]) -> String {
+    let mut output = String::new();
+    for spans in lines {
+        // Each line starts with the default style, so it can be shown on its own.
+        let mut style = Style::new();
+        for span in spans {
+            if span.style != style {
+                style = span.style;
+                write_graphic_rendition(&mut output, style);
+            }
+            output.push_str(&span.text);
+        }
+        if style != Style::new() {
+            output.push_str("\x1b[0m");
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Writes SGR sequence, which resets the style and sets all its attributes and colors.
+fn write_graphic_rendition(output: &mut String, style: Style) {
+    const CODES: [(Attributes, &str); 10] = [
+        (Attributes::BOLD, "1"),
+        (Attributes::FAINT, "2"),
+        (Attributes::ITALIC, "3"),
+        (Attributes::UNDERLINE, "4"),
+        (Attributes::DOUBLE_UNDERLINE, "4:2"),
+        (Attributes::CURLY_UNDERLINE, "4:3"),
+        (Attributes::BLINK, "5"),
+        (Attributes::INVERSE, "7"),
+        (Attributes::INVISIBLE, "8"),
+        (Attributes::STRIKETHROUGH, "9"),
+    ];
+
+    output.push_str("\x1b[0");
+    for (attribute, code) in CODES {
+        if style.attributes().contains(attribute) {
+            output.push(';');
+            output.push_str(code);
+        }
+    }
+    write_color(output, style.foreground(), 30);
+    write_color(output, style.background(), 40);
+    output.push('m');
+}
+
+/// Writes parameters of the color, where base is the code of the first basic color.
+fn write_color(output: &mut String, color: Color, base: u8) {
+    // Writing to a string cannot fail.
+    let _ = match color {
+        Color::Default => Ok(()),
+        Color::Indexed(index @ 0..=7) => write!(output, ";{}", base + index),
+        Color::Indexed(index @ 8..=15) => write!(output, ";{}", base + 60 + index - 8),
+        Color::Indexed(index) => write!(output, ";{};5;{}", base + 8, index),
+        Color::Rgb(rgb) => write!(
+            output,
+            ";{};2;{};{};{}",
+            base + 8,
+            rgb.red(),
+            rgb.green(),
+            rgb.blue()
+        ),
+    };
+}
+
+fn dump_html(lines: &[Vec<Span>], palette: DumpPalette) -> String {
+    let mut output = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>ft</title>\n</head>\n",
+    );
+    output.push_str("<body>\n<pre style=\"");
+    output.push_str(&format!(
+        "color: {}; background-color: {}; padding: 1em",
+        css_color(palette.foreground),
+        css_color(palette.background)
+    ));
+    output.push_str("\">");
+    for spans in lines {
+        for span in spans {
+            let style = css_style(span.style, palette);
+            if style.is_empty() {
+                push_escaped(&mut output, &span.text);
+            } else {
+                output.push_str(&format!("<span style=\"{}\">", style));
+                push_escaped(&mut output, &span.text);
+                output.push_str("</span>");
+            }
+        }
+        output.push('\n');
+    }
+    output.push_str("</pre>\n</body>\n</html>\n");
+    output
+}
+
+/// Returns inline style of the span, leaving out properties equal to those of the whole page.
+fn css_style(style: Style, palette: DumpPalette) -> String {
+    let attributes = style.attributes();
+    let mut foreground = style.foreground().to_rgb(palette.foreground);
+    let mut background = style.background().to_rgb(palette.background);
+    if attributes.contains(Attributes::INVERSE) {
+        mem::swap(&mut foreground, &mut background);
+    }
+    if attributes.contains(Attributes::FAINT) {
+        foreground = foreground.with_alpha(palette.faint_alpha).blend(background);
+    }
+    if attributes.contains(Attributes::INVISIBLE) {
+        foreground = background;
+    }
+
+    let mut properties = Vec::new();
+    if foreground != palette.foreground {
+        properties.push(format!("color: {}", css_color(foreground)));
+    }
+    if background != palette.background {
+        properties.push(format!("background-color: {}", css_color(background)));
+    }
+    if attributes.contains(Attributes::BOLD) {
+        properties.push("font-weight: bold".to_owned());
+    }
+    if attributes.contains(Attributes::ITALIC) {
+        properties.push("font-style: italic".to_owned());
+    }
+
+    let mut decoration = Vec::new();
+    if attributes.intersects(Attributes::ALL_UNDERLINES) {
+        decoration.push("underline");
+    }
+    if attributes.contains(Attributes::STRIKETHROUGH) {
+        decoration.push("line-through");
+    }
+    if attributes.contains(Attributes::DOUBLE_UNDERLINE) {
+        decoration.push("double");
+    } else if attributes.contains(Attributes::CURLY_UNDERLINE) {
+        decoration.push("wavy");
+    }
+    if !decoration.is_empty() {
+        properties.push(format!("text-decoration: {}", decoration.join(" ")));
+    }
+    properties.join("; ")
+}
+
+fn css_color(rgb: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb.red(), rgb.green(), rgb.blue())
+}
+
+fn push_escaped(output: &mut String, text: &str) {
+    for character in text.chars() {
+        match character {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            _ => output.push(character),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::parser::GraphicRendition;
+
+    fn line(text: &str, style: Style, length: usize, wrapped: bool) -> Line {
+        let mut line = Line::new(length);
+        for (index, character) in text.chars().enumerate() {
+            line.write(index, Some(character), CellWidth::Single, style);
+        }
+        line.set_wrapped(wrapped);
+        line
+    }
+
+    const PALETTE: DumpPalette = DumpPalette {
+        foreground: Rgb::new(255, 255, 255),
+        background: Rgb::new(0, 0, 0),
+        faint_alpha: Alpha::new(128),
+    };
+
+    fn red() -> Style {
+        let mut style = Style::new();
+        style.apply(GraphicRendition::Foreground(Color::Indexed(1)));
+        style
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(DumpFormat::Plain, "plain".parse().unwrap());
+        assert_eq!(DumpFormat::Ansi, "ansi".parse().unwrap());
+        assert_eq!(DumpFormat::Html, "html".parse().unwrap());
+        assert!("pdf".parse::<DumpFormat>().is_err());
+    }
+
+    #[test]
+    fn test_dump_plain() {
+        let lines = [
+            line("ab  ", Style::new(), 4, false),
+            line("cdef", Style::new(), 4, true),
+            line("gh", Style::new(), 4, false),
+            line("", Style::new(), 4, false),
+            line("", Style::new(), 4, false),
+        ];
+        assert_eq!("ab\ncdefgh\n", dump(&lines, DumpFormat::Plain, PALETTE));
+    }
+
+    #[test]
+    fn test_dump_ansi() {
+        let mut line = line("ab", red(), 4, false);
+        line.write(1, Some('b'), CellWidth::Single, Style::new());
+        let mut background = Style::new();
+        background.apply(GraphicRendition::Background(Color::Indexed(12)));
+        line.erase(3..4, background);
+        assert_eq!(
+            "\x1b[0;31ma\x1b[0mb \x1b[0;104m \x1b[0m\n",
+            dump(&[line], DumpFormat::Ansi, PALETTE)
+        );
+    }
+
+    #[test]
+    fn test_write_graphic_rendition() {
+        let mut style = Style::new();
+        style.apply(GraphicRendition::SetAttributes(
+            Attributes::BOLD.union(Attributes::CURLY_UNDERLINE),
+        ));
+        style.apply(GraphicRendition::Foreground(Color::Indexed(200)));
+        style.apply(GraphicRendition::Background(Color::Rgb(Rgb::new(1, 2, 3))));
+        let mut output = String::new();
+        write_graphic_rendition(&mut output, style);
+        assert_eq!("\x1b[0;1;4:3;38;5;200;48;2;1;2;3m", output);
+    }
+
+    #[test]
+    fn test_dump_html() {
+        let lines = [line("a<b", red(), 3, false)];
+        let html = dump(&lines, DumpFormat::Html, PALETTE);
+        assert!(html.contains("<pre style=\"color: #ffffff; background-color: #000000;"));
+        assert!(html.contains("\"><span style=\"color: #cd0000\">a&lt;b</span>\n</pre>"));
+    }
+}
@@ -1,52 +1,236 @@
-use crate::spatial::point::Point;
-use crate::spatial::CellsUnit;
+use std::ops::Range;
+use std::slice;
+
+use crate::terminal::cells::style::Style;
 
 #[derive(Debug, Clone)]
 pub struct Line {
     cells: Vec<Cell>,
+    /// Whether the line was wrapped at the right margin, i.e. it continues in the next line.
+    wrapped: bool,
 }
 
 impl Line {
     pub fn new(length: usize) -> Self {
         let cells = vec![Cell::new(); length];
-        Self { cells }
+        Self::from_cells(cells, false)
+    }
+
+    pub fn from_cells(cells: Vec<Cell>, wrapped: bool) -> Self {
+        let length = cells.len();
+        let mut line = Self { cells, wrapped };
+        line.repair_double_width(0..length);
+        line
+    }
+
+    pub fn into_cells(self) -> Vec<Cell> {
+        self.cells
+    }
+
+    pub fn is_wrapped(&self) -> bool {
+        self.wrapped
+    }
+
+    pub fn set_wrapped(&mut self, wrapped: bool) {
+        self.wrapped = wrapped;
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.cells.iter().all(Cell::is_blank)
+    }
+
+    /// Truncates or extends the line with empty cells to the given length.
+    pub fn resize(&mut self, length: usize) {
+        self.cells.resize(length, Cell::new());
+        self.repair_double_width(length.saturating_sub(1)..length);
     }
 
-    pub fn iter(&self) -> std::slice::Iter<Cell> {
+    pub fn iter(&self) -> std::slice::Iter<'_, Cell> {
         self.cells.iter()
     }
 
-    pub fn cell_mut(&mut self, index: usize) -> &mut Cell {
-        &mut self.cells[index]
+    pub fn cell(&self, index: usize) -> &Cell {
+        &self.cells[index]
+    }
+
+    /// Writes the character at index, overwriting the following cell with a spacer if the
+    /// character is double width. Returns true if other cells had to be erased, because they were
+    /// halves of overwritten double width characters.
+    pub fn write(
+        &mut self,
+        index: usize,
+        character: Option<char>,
+        width: CellWidth,
+        style: Style,
+    ) -> bool {
+        let mut cell = Cell::with_style(style);
+        cell.character = character;
+        cell.width = width;
+        self.cells[index] = cell;
+        let mut end = index + 1;
+        if width == CellWidth::Double {
+            let mut spacer = Cell::with_style(style);
+            spacer.width = CellWidth::Spacer;
+            self.cells[end] = spacer;
+            end += 1;
+        }
+        self.repair_double_width(index..end)
+    }
+
+    /// Attaches the zero width character to the character at index. Returns false if there is no
+    /// character to attach it to.
+    pub fn attach_mark(&mut self, index: usize, mark: char) -> bool {
+        let cell = &mut self.cells[index];
+        if cell.character.is_none() {
+            return false;
+        }
+        cell.marks.push(mark);
+        true
+    }
+
+    pub fn clear(&mut self, style: Style) {
+        self.erase(0..self.cells.len(), style);
+        self.wrapped = false;
+    }
+
+    /// Shifts cells starting at index to the right, inserting erased cells in their place. Cells
+    /// shifted past the end of the line are lost.
+    pub fn insert_cells(&mut self, index: usize, count: usize, style: Style) {
+        let index = index.min(self.cells.len());
+        let count = count.min(self.cells.len() - index);
+        self.cells[index..].rotate_right(count);
+        self.erase(index..index + count, style);
+        let length = self.cells.len();
+        self.repair_double_width(length.saturating_sub(1)..length);
+    }
+
+    /// Removes cells starting at index, shifting the remaining cells to the left and filling the
+    /// end of the line with erased cells.
+    pub fn delete_cells(&mut self, index: usize, count: usize, style: Style) {
+        let index = index.min(self.cells.len());
+        let count = count.min(self.cells.len() - index);
+        self.cells[index..].rotate_left(count);
+        self.repair_double_width(index..index);
+        let length = self.cells.len();
+        self.erase(length - count..length, style);
     }
 
-    pub fn clear(&mut self) {
-        for cell in &mut self.cells {
-            cell.character = None;
+    /// Erases cells in the given range, leaving them with the given style.
+    pub fn erase(&mut self, range: Range<usize>, style: Style) {
+        let end = range.end.min(self.cells.len());
+        let start = range.start.min(end);
+        for cell in &mut self.cells[start..end] {
+            *cell = Cell::with_style(style);
         }
+        self.repair_double_width(start..end);
+    }
+
+    /// Erases halves of double width characters around the given range, which lost their other
+    /// half. Returns true if any cell was erased.
+    fn repair_double_width(&mut self, range: Range<usize>) -> bool {
+        let start = range.start.saturating_sub(1);
+        let end = (range.end + 1).min(self.cells.len());
+        let mut repaired = false;
+        for index in start..end {
+            let orphaned = match self.cells[index].width {
+                CellWidth::Single => false,
+                CellWidth::Double => self
+                    .cells
+                    .get(index + 1)
+                    .is_none_or(|cell| cell.width != CellWidth::Spacer),
+                CellWidth::Spacer => index == 0 || self.cells[index - 1].width != CellWidth::Double,
+            };
+            if orphaned {
+                self.cells[index] = Cell::with_style(self.cells[index].style);
+                repaired = true;
+            }
+        }
+        repaired
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Number of columns taken by the character of a cell.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CellWidth {
+    Single,
+    /// First half of a double width character.
+    Double,
+    /// Second half of a double width character, which holds no character of its own.
+    Spacer,
+}
+
+#[derive(Debug, Clone)]
 pub struct Cell {
     character: Option<char>,
+    marks: Marks,
+    style: Style,
+    width: CellWidth,
 }
 
 impl Cell {
     pub fn new() -> Self {
-        Self { character: None }
+        Self::with_style(Style::new())
     }
 
-    pub fn character_mut(&mut self) -> &mut Option<char> {
-        &mut self.character
+    pub fn with_style(style: Style) -> Self {
+        Self {
+            character: None,
+            marks: Marks::Empty,
+            style,
+            width: CellWidth::Single,
+        }
     }
 
     pub fn character(&self) -> Option<char> {
         self.character
     }
+
+    /// Returns zero width characters, like combining marks, following the character.
+    pub fn marks(&self) -> &[char] {
+        self.marks.as_slice()
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn width(&self) -> CellWidth {
+        self.width
+    }
+
+    /// Returns true if rendering of the cell would not differ from the empty background.
+    pub fn is_blank(&self) -> bool {
+        self.character.is_none() && self.style == Style::new() && self.width == CellWidth::Single
+    }
+}
+
+/// Zero width characters attached to the character of a cell. Most clusters have at most one
+/// mark, so it is stored inline and only longer sequences are allocated.
+#[derive(Debug, Clone)]
+enum Marks {
+    Empty,
+    Single(char),
+    Overflow(Vec<char>),
 }
 
-pub enum RendererAction {
-    RenderAll,
-    RenderCell(Point<CellsUnit>),
+impl Marks {
+    /// Limits the number of marks per cell, so a stream of them cannot grow it without bound.
+    const CAPACITY: usize = 16;
+
+    fn push(&mut self, mark: char) {
+        match self {
+            Marks::Empty => *self = Marks::Single(mark),
+            Marks::Single(first) => *self = Marks::Overflow(vec![*first, mark]),
+            Marks::Overflow(marks) if marks.len() < Self::CAPACITY => marks.push(mark),
+            Marks::Overflow(_) => {}
+        }
+    }
+
+    fn as_slice(&self) -> &[char] {
+        match self {
+            Marks::Empty => &[],
+            Marks::Single(mark) => slice::from_ref(mark),
+            Marks::Overflow(marks) => marks,
+        }
+    }
 }
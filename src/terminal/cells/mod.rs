@@ -1,62 +1,209 @@
-use std::collections::vec_deque::Iter;
 use std::collections::VecDeque;
+use std::mem;
+use std::ops::Range;
+
+use unicode_width::UnicodeWidthChar;
 
 use crate::spatial::dimension::Dimensions;
 use crate::spatial::point::Point;
 use crate::spatial::CellsUnit;
-use crate::terminal::cells::line::{Cell, Line, RendererAction};
+use crate::terminal::cells::charset::{Charset, CharsetSlot, Charsets};
+use crate::terminal::cells::damage::{Damage, RendererAction};
+use crate::terminal::cells::dump::{DumpFormat, DumpPalette};
+use crate::terminal::cells::line::{Cell, CellWidth, Line};
+use crate::terminal::cells::style::Style;
+
+const ZERO_WIDTH_JOINER: char = '\u{200d}';
 
+pub mod charset;
+pub mod damage;
+pub mod dump;
 pub mod line;
+mod reflow;
+pub mod style;
 
 #[derive(Debug)]
 pub struct Cells {
     size: Dimensions<CellsUnit>,
     lines: VecDeque<Line>,
+    /// Lines scrolled off the top of the primary screen, the oldest first.
+    scrollback: VecDeque<Line>,
+    scrollback_capacity: usize,
+    /// Number of scrollback lines shown above the screen.
+    viewport_offset: usize,
+    /// Lines of the screen which is not displayed, i.e. the primary screen while the alternate
+    /// one is active and vice versa.
+    inactive_lines: VecDeque<Line>,
+    alternate_screen: bool,
     current_cell: Point<CellsUnit>,
+    /// Set after writing the last column, where the cursor stays until the next character wraps
+    /// it to the following line. Any cursor movement clears it.
+    pending_wrap: bool,
+    saved_cursor: SavedCursor,
+    /// Cursor saved on the inactive screen, as each screen has its own.
+    inactive_saved_cursor: SavedCursor,
+    style: Style,
+    charsets: Charsets,
+    insert_mode: bool,
+    origin_mode: bool,
+    scroll_region: Range<usize>,
+    damage: Damage,
+}
+
+/// State restored together with the cursor position.
+#[derive(Debug, Copy, Clone)]
+struct SavedCursor {
+    cell: Point<CellsUnit>,
+    style: Style,
+    charsets: Charsets,
+    origin_mode: bool,
+}
+
+impl SavedCursor {
+    fn new() -> Self {
+        Self {
+            cell: Point::new(0, 0),
+            style: Style::new(),
+            charsets: Charsets::new(),
+            origin_mode: false,
+        }
+    }
 }
 
 impl Cells {
-    pub fn new(size: Dimensions<CellsUnit>) -> Self {
+    pub fn new(size: Dimensions<CellsUnit>, scrollback_capacity: usize) -> Self {
         assert!(size.width() >= 1 && size.height() >= 1);
 
         let lines = VecDeque::from(vec![
             Line::new(size.width() as usize);
             size.height() as usize
         ]);
+        let inactive_lines = lines.clone();
         let first_cell = Point::new(0, 0);
         Self {
             size,
             lines,
+            scrollback: VecDeque::new(),
+            scrollback_capacity,
+            viewport_offset: 0,
+            inactive_lines,
+            alternate_screen: false,
             current_cell: first_cell,
+            pending_wrap: false,
+            saved_cursor: SavedCursor::new(),
+            inactive_saved_cursor: SavedCursor::new(),
+            style: Style::new(),
+            charsets: Charsets::new(),
+            insert_mode: false,
+            origin_mode: false,
+            scroll_region: 0..size.height() as usize,
+            damage: Damage::new(size),
         }
     }
 
-    pub fn push_character(&mut self, character: Option<char>) -> RendererAction {
+    pub fn push_character(&mut self, character: Option<char>) {
+        let character = character.map(|character| self.charsets.translate(character));
+        if character.is_some_and(|character| self.attach_to_previous_cell(character)) {
+            return;
+        }
+
+        let width = match character.and_then(UnicodeWidthChar::width) {
+            Some(2) if self.size.width() > 1 => CellWidth::Double,
+            _ => CellWidth::Single,
+        };
+        let columns = if width == CellWidth::Double { 2 } else { 1 };
+
+        if self.pending_wrap
+            || self.current_cell.horizontal_distance() + columns > self.size.width()
+        {
+            // Double width character does not fit at the end of the line, so it is moved to the
+            // next one.
+            self.wrap_line();
+        }
+
         let cell_point = self.current_cell;
+        if self.insert_mode {
+            self.insert_characters(columns);
+        }
 
-        let cell = self.cell_mut(cell_point);
-        *cell.character_mut() = character;
+        let style = self.style;
+        let (cell_index, line) = self.current_line_mut();
+        let neighbours_erased = line.write(cell_index, character, width, style);
+        let line_index = cell_point.vertical_distance() as usize;
+        if neighbours_erased {
+            self.damage_lines(line_index..line_index + 1);
+        } else {
+            let columns = columns as usize;
+            self.damage_cells(line_index, cell_index..cell_index + columns);
+        }
 
-        if self.current_cell.horizontal_distance() == self.size.width() - 1 {
-            self.carriage_return();
-            let action = self.new_line();
-            if let Some(action) = action {
-                return action;
-            }
+        if cell_point.horizontal_distance() + columns == self.size.width() {
+            self.current_cell = Point::new(self.size.width() - 1, cell_point.vertical_distance());
+            self.pending_wrap = true;
         } else {
-            self.current_cell = self.current_cell.shifted(1, 0);
+            self.current_cell = self.current_cell.shifted(columns as i32, 0);
             log::trace!("New current cell: {:?}", self.current_cell);
         }
-        RendererAction::RenderCell(cell_point)
+    }
+
+    /// Attaches a zero width character, or any character following a zero width joiner, to the
+    /// character before the cursor. Returns false if the character needs a cell of its own.
+    fn attach_to_previous_cell(&mut self, character: char) -> bool {
+        let Some(point) = self.previous_cell() else {
+            return false;
+        };
+        let line_index = point.vertical_distance() as usize;
+        let line = &mut self.lines[line_index];
+        let index = point.horizontal_distance() as usize;
+        let joined = line.cell(index).marks().last() == Some(&ZERO_WIDTH_JOINER);
+        if UnicodeWidthChar::width(character) != Some(0) && !joined {
+            return false;
+        }
+        let attached = line.attach_mark(index, character);
+        if attached {
+            self.damage_cells(line_index, index..index + 1);
+        }
+        attached
+    }
+
+    /// Returns the cell before the cursor, continuing to the end of the previous line if the
+    /// cursor was wrapped. Cursor waiting to wrap stays on the last written cell.
+    fn previous_cell(&self) -> Option<Point<CellsUnit>> {
+        let horizontal_distance = self.current_cell.horizontal_distance();
+        let vertical_distance = self.current_cell.vertical_distance();
+        let mut point = if self.pending_wrap {
+            self.current_cell
+        } else if horizontal_distance > 0 {
+            Point::new(horizontal_distance - 1, vertical_distance)
+        } else if vertical_distance > 0 && self.lines[vertical_distance as usize - 1].is_wrapped() {
+            Point::new(self.size.width() - 1, vertical_distance - 1)
+        } else {
+            return None;
+        };
+        // Spacer belongs to the double width character preceding it.
+        if self.cell(point).width() == CellWidth::Spacer {
+            point = point.shifted(-1, 0);
+        }
+        Some(point)
+    }
+
+    /// Marks the current line as wrapped and moves to the beginning of the next one.
+    fn wrap_line(&mut self) {
+        let line_index = self.current_cell.vertical_distance() as usize;
+        self.lines[line_index].set_wrapped(true);
+        self.carriage_return();
+        self.new_line();
     }
 
     pub fn carriage_return(&mut self) {
         let vertical_distance = self.current_cell.vertical_distance();
         self.current_cell = Point::new(0, vertical_distance);
+        self.pending_wrap = false;
         log::trace!("New current cell: {:?}", self.current_cell);
     }
 
     pub fn move_back(&mut self) {
+        self.pending_wrap = false;
         if self.current_cell.horizontal_distance() == 0 {
             let vertical_distance = self.current_cell.vertical_distance();
             if vertical_distance > 0 {
@@ -69,46 +216,904 @@ impl Cells {
         }
     }
 
+    /// Moves cursor one line up, scrolling the region down if the cursor is at its top.
+    pub fn reverse_index(&mut self) {
+        let line = self.current_cell.vertical_distance() as usize;
+        if line == self.scroll_region.start {
+            self.scroll_down(1);
+        } else {
+            self.move_up();
+        }
+    }
+
     pub fn move_up(&mut self) {
+        self.pending_wrap = false;
         if self.current_cell.vertical_distance() > 0 {
             self.current_cell = self.current_cell.shifted(0, -1);
             log::trace!("New current cell: {:?}", self.current_cell);
         }
     }
 
-    pub fn new_line(&mut self) -> Option<RendererAction> {
-        if self.current_cell.vertical_distance() == self.size.height() - 1 {
-            self.lines.rotate_left(1);
-            let line = self.lines.back_mut().expect("Height is at least 1");
-            line.clear();
-            log::trace!("Lines rotated");
-            Some(RendererAction::RenderAll)
+    /// Moves cursor to the given cell, clamping it to the terminal size.
+    pub fn move_to(&mut self, cell: Point<CellsUnit>) {
+        self.current_cell = self.clamp(cell);
+        self.pending_wrap = false;
+        log::trace!("New current cell: {:?}", self.current_cell);
+    }
+
+    /// Moves cursor to the position addressed relatively to the origin, which is the top of the
+    /// scroll region in origin mode and the first line otherwise.
+    pub fn move_to_position(&mut self, position: Point<CellsUnit>) {
+        if !self.origin_mode {
+            self.move_to(position);
+            return;
+        }
+
+        let top = self.scroll_region.start as u32;
+        let bottom = self.scroll_region.end as u32 - 1;
+        let vertical_distance = top.saturating_add(position.vertical_distance()).min(bottom);
+        self.move_to(Point::new(
+            position.horizontal_distance(),
+            vertical_distance,
+        ));
+    }
+
+    /// Returns cursor position relative to the origin, see [`Cells::move_to_position`].
+    pub fn cursor_position(&self) -> Point<CellsUnit> {
+        if !self.origin_mode {
+            return self.current_cell;
+        }
+
+        let top = self.scroll_region.start as u32;
+        let vertical_distance = self.current_cell.vertical_distance().saturating_sub(top);
+        Point::new(self.current_cell.horizontal_distance(), vertical_distance)
+    }
+
+    pub fn move_to_column(&mut self, column: u32) {
+        let vertical_distance = self.current_cell.vertical_distance();
+        self.move_to(Point::new(column, vertical_distance));
+    }
+
+    pub fn move_to_line(&mut self, line: u32) {
+        let horizontal_distance = self.current_cell.horizontal_distance();
+        self.move_to(Point::new(horizontal_distance, line));
+    }
+
+    /// Moves cursor up by the count of lines, stopping at the top of the scroll region if the
+    /// cursor is inside or below it, so lines above the region are kept intact.
+    pub fn move_up_by(&mut self, count: u32) {
+        let line = self.current_cell.vertical_distance();
+        let top = self.scroll_region.start as u32;
+        let limit = if line >= top { top } else { 0 };
+        self.move_to_line(line.saturating_sub(count).max(limit));
+    }
+
+    /// Moves cursor down by the count of lines, stopping at the bottom of the scroll region if the
+    /// cursor is inside or above it, so lines below the region are kept intact.
+    pub fn move_down_by(&mut self, count: u32) {
+        let line = self.current_cell.vertical_distance();
+        let bottom = self.scroll_region.end as u32 - 1;
+        let limit = if line <= bottom {
+            bottom
         } else {
+            self.size.height() - 1
+        };
+        self.move_to_line(line.saturating_add(count).min(limit));
+    }
+
+    /// Moves cursor one line down, scrolling the region up if the cursor is at its bottom.
+    pub fn new_line(&mut self) {
+        self.pending_wrap = false;
+        let line = self.current_cell.vertical_distance() as usize;
+        if line + 1 == self.scroll_region.end {
+            self.scroll_up(1);
+        } else if line + 1 < self.size.height() as usize {
             self.current_cell = self.current_cell.shifted(0, 1);
             log::trace!("New current cell: {:?}", self.current_cell);
-            None
         }
     }
 
-    pub fn iter(&self) -> Iter<Line> {
-        self.lines.iter()
+    /// Scrolls content of the scroll region up, adding blank lines at its bottom.
+    pub fn scroll_up(&mut self, count: u32) {
+        let region = self.scroll_region();
+        let count = (count as usize).min(region.len());
+        self.lines.make_contiguous()[region.clone()].rotate_left(count);
+        self.damage_scroll(region.clone(), count as i32);
+        let scrolled_off = region.end - count..region.end;
+        // Only lines leaving the top of the primary screen are kept, so the content of full-screen
+        // programs does not pollute the scrollback.
+        if region.start == 0 && !self.alternate_screen {
+            self.push_scrollback(scrolled_off.clone());
+        }
+        self.clear_lines(scrolled_off);
+        log::trace!("Lines scrolled up by {}", count);
+    }
+
+    /// Scrolls content of the scroll region down, adding blank lines at its top.
+    pub fn scroll_down(&mut self, count: u32) {
+        let region = self.scroll_region();
+        let count = (count as usize).min(region.len());
+        self.lines.make_contiguous()[region.clone()].rotate_right(count);
+        self.damage_scroll(region.clone(), -(count as i32));
+        self.clear_lines(region.start..region.start + count);
+        log::trace!("Lines scrolled down by {}", count);
     }
 
-    pub fn clear(&mut self) {
+    /// Sets scroll region to lines from top to bottom (exclusive) and moves cursor to the first
+    /// cell. Regions smaller than two lines are ignored.
+    pub fn set_scroll_region(&mut self, top: u32, bottom: Option<u32>) {
+        let height = self.size.height();
+        let bottom = bottom.unwrap_or(height).min(height);
+        if top + 1 >= bottom {
+            log::debug!("Ignoring invalid scroll region: {}..{}", top, bottom);
+            return;
+        }
+
+        self.scroll_region = top as usize..bottom as usize;
+        self.move_to_position(Point::new(0, 0));
+    }
+
+    fn push_scrollback(&mut self, range: Range<usize>) {
+        if self.scrollback_capacity == 0 {
+            return;
+        }
+
+        for index in range {
+            if self.scrollback.len() == self.scrollback_capacity {
+                self.scrollback.pop_front();
+            }
+            self.scrollback.push_back(self.lines[index].clone());
+            if self.viewport_offset > 0 {
+                // Keep the viewport at the same content while new lines are added below it.
+                self.viewport_offset = (self.viewport_offset + 1).min(self.scrollback.len());
+                self.damage.damage_all();
+            }
+        }
+    }
+
+    pub fn clear_scrollback(&mut self) {
+        self.scrollback.clear();
+        self.set_viewport_offset(0);
+    }
+
+    /// Scrolls viewport back into the scrollback history by the given number of lines.
+    pub fn scroll_viewport_up(&mut self, count: usize) {
+        let offset = self.viewport_offset.saturating_add(count);
+        self.set_viewport_offset(offset.min(self.scrollback.len()));
+    }
+
+    /// Scrolls viewport forward towards the screen by the given number of lines.
+    pub fn scroll_viewport_down(&mut self, count: usize) {
+        self.set_viewport_offset(self.viewport_offset.saturating_sub(count));
+    }
+
+    pub fn scroll_viewport_to_bottom(&mut self) {
+        self.set_viewport_offset(0);
+    }
+
+    fn set_viewport_offset(&mut self, offset: usize) {
+        if offset != self.viewport_offset {
+            self.viewport_offset = offset;
+            self.damage.damage_all();
+            log::trace!("Viewport offset: {}", self.viewport_offset);
+        }
+    }
+
+    pub fn is_viewport_scrolled(&self) -> bool {
+        self.viewport_offset > 0
+    }
+
+    /// Returns cursor cell in the viewport, unless the cursor is scrolled out of it.
+    pub fn viewport_cursor(&self) -> Option<Point<CellsUnit>> {
+        let vertical_distance =
+            self.current_cell.vertical_distance() as usize + self.viewport_offset;
+        if vertical_distance >= self.size.height() as usize {
+            return None;
+        }
+        let horizontal_distance = self.current_cell.horizontal_distance();
+        Some(Point::new(horizontal_distance, vertical_distance as u32))
+    }
+
+    /// Returns line of the viewport with the given index.
+    pub fn viewport_line(&self, index: u32) -> &Line {
+        let index = index as usize;
+        if index < self.viewport_offset {
+            &self.scrollback[self.scrollback.len() - self.viewport_offset + index]
+        } else {
+            &self.lines[index - self.viewport_offset]
+        }
+    }
+
+    /// Serializes lines of the screen, preceded by the scrollback if requested. Scrollback belongs
+    /// to the primary screen, so it is left out while the alternate one is active.
+    pub fn dump(
+        &self,
+        format: DumpFormat,
+        include_scrollback: bool,
+        palette: DumpPalette,
+    ) -> String {
+        let scrollback = if include_scrollback && !self.alternate_screen {
+            self.scrollback.range(..)
+        } else {
+            self.scrollback.range(0..0)
+        };
+        dump::dump(scrollback.chain(&self.lines), format, palette)
+    }
+
+    /// Returns cells of the viewport changed since the last call.
+    pub fn take_damage(&mut self) -> Vec<RendererAction> {
+        let mut actions = self.damage.take(self.viewport_cursor());
+        for action in &mut actions {
+            let RendererAction::RenderCells { line, cells } = action else {
+                continue;
+            };
+            // Double width character is rendered from its first half, so it has to be included.
+            let line = self.viewport_line(*line);
+            if cells.start > 0 && line.cell(cells.start as usize).width() == CellWidth::Spacer {
+                cells.start -= 1;
+            }
+        }
+        actions
+    }
+
+    pub fn erase_below(&mut self) {
+        self.erase_line_right();
+        let style = self.style.erased();
+        let below = self.current_cell.vertical_distance() as usize + 1;
+        for line in self.lines.range_mut(below..) {
+            line.clear(style);
+        }
+        self.damage_lines(below..self.lines.len());
+    }
+
+    pub fn erase_above(&mut self) {
+        self.erase_line_left();
+        let style = self.style.erased();
+        let above = self.current_cell.vertical_distance() as usize;
+        for line in self.lines.range_mut(..above) {
+            line.clear(style);
+        }
+        self.damage_lines(0..above);
+    }
+
+    pub fn erase_all(&mut self) {
+        let style = self.style.erased();
         for line in &mut self.lines {
-            line.clear();
+            line.clear(style);
+        }
+        self.damage_lines(0..self.lines.len());
+    }
+
+    pub fn erase_line_right(&mut self) {
+        let start = self.current_cell.horizontal_distance() as usize;
+        let end = self.size.width() as usize;
+        self.erase_in_current_line(start..end);
+    }
+
+    pub fn erase_line_left(&mut self) {
+        let end = self.current_cell.horizontal_distance() as usize + 1;
+        self.erase_in_current_line(0..end);
+    }
+
+    pub fn erase_line(&mut self) {
+        let end = self.size.width() as usize;
+        self.erase_in_current_line(0..end);
+    }
+
+    /// Erases given number of characters starting from the cursor without moving it.
+    pub fn erase_characters(&mut self, count: u32) {
+        let start = self.current_cell.horizontal_distance() as usize;
+        let end = start.saturating_add(count as usize);
+        self.erase_in_current_line(start..end);
+    }
+
+    pub fn insert_characters(&mut self, count: u32) {
+        let style = self.style.erased();
+        let (index, line) = self.current_line_mut();
+        line.insert_cells(index, count as usize, style);
+        self.damage_current_line_from(index);
+    }
+
+    pub fn delete_characters(&mut self, count: u32) {
+        let style = self.style.erased();
+        let (index, line) = self.current_line_mut();
+        line.delete_cells(index, count as usize, style);
+        self.damage_current_line_from(index);
+    }
+
+    /// Inserts blank lines at the cursor, shifting the following lines of the scroll region down.
+    pub fn insert_lines(&mut self, count: u32) {
+        let region = self.scroll_region();
+        let line = self.current_cell.vertical_distance() as usize;
+        if !region.contains(&line) {
+            return;
+        }
+
+        let count = (count as usize).min(region.end - line);
+        self.lines.make_contiguous()[line..region.end].rotate_right(count);
+        self.damage_scroll(line..region.end, -(count as i32));
+        self.clear_lines(line..line + count);
+        self.carriage_return();
+    }
+
+    /// Deletes lines at the cursor, shifting the following lines of the scroll region up.
+    pub fn delete_lines(&mut self, count: u32) {
+        let region = self.scroll_region();
+        let line = self.current_cell.vertical_distance() as usize;
+        if !region.contains(&line) {
+            return;
+        }
+
+        let count = (count as usize).min(region.end - line);
+        self.lines.make_contiguous()[line..region.end].rotate_left(count);
+        self.damage_scroll(line..region.end, count as i32);
+        self.clear_lines(region.end - count..region.end);
+        self.carriage_return();
+    }
+
+    pub fn switch_to_alternate_screen(&mut self) {
+        if !self.alternate_screen {
+            self.switch_screen();
+        }
+    }
+
+    pub fn switch_to_primary_screen(&mut self) {
+        if self.alternate_screen {
+            self.switch_screen();
+        }
+    }
+
+    fn switch_screen(&mut self) {
+        mem::swap(&mut self.lines, &mut self.inactive_lines);
+        mem::swap(&mut self.saved_cursor, &mut self.inactive_saved_cursor);
+        self.alternate_screen = !self.alternate_screen;
+        self.damage_lines(0..self.lines.len());
+        log::debug!("Alternate screen active: {}", self.alternate_screen);
+    }
+
+    pub fn is_alternate_screen(&self) -> bool {
+        self.alternate_screen
+    }
+
+    pub fn save_cursor(&mut self) {
+        self.saved_cursor = SavedCursor {
+            cell: self.current_cell,
+            style: self.style,
+            charsets: self.charsets,
+            origin_mode: self.origin_mode,
+        };
+    }
+
+    pub fn restore_cursor(&mut self) {
+        let saved_cursor = self.saved_cursor;
+        self.style = saved_cursor.style;
+        self.charsets = saved_cursor.charsets;
+        self.origin_mode = saved_cursor.origin_mode;
+        self.move_to(saved_cursor.cell);
+    }
+
+    pub fn designate_charset(&mut self, slot: CharsetSlot, charset: Charset) {
+        self.charsets.designate(slot, charset);
+    }
+
+    pub fn shift_charset(&mut self, slot: CharsetSlot) {
+        self.charsets.shift(slot);
+    }
+
+    pub fn set_origin_mode(&mut self, origin_mode: bool) {
+        self.origin_mode = origin_mode;
+        self.move_to_position(Point::new(0, 0));
+    }
+
+    pub fn set_insert_mode(&mut self, insert_mode: bool) {
+        self.insert_mode = insert_mode;
+    }
+
+    fn scroll_region(&self) -> Range<usize> {
+        self.scroll_region.clone()
+    }
+
+    fn clear_lines(&mut self, range: Range<usize>) {
+        let style = self.style.erased();
+        for line in self.lines.range_mut(range.clone()) {
+            line.clear(style);
+        }
+        self.damage_lines(range);
+    }
+
+    fn current_line_mut(&mut self) -> (usize, &mut Line) {
+        let index = self.current_cell.horizontal_distance() as usize;
+        let line_index = self.current_cell.vertical_distance() as usize;
+        (index, &mut self.lines[line_index])
+    }
+
+    /// Damages cells of the current line starting at index, including the one before it, which
+    /// could be erased as the first half of a split double width character.
+    fn damage_current_line_from(&mut self, index: usize) {
+        let line_index = self.current_cell.vertical_distance() as usize;
+        let width = self.size.width() as usize;
+        self.damage_cells(line_index, index.saturating_sub(1)..width);
+    }
+
+    fn erase_in_current_line(&mut self, range: Range<usize>) {
+        let style = self.style.erased();
+        let line_index = self.current_cell.vertical_distance() as usize;
+        self.lines[line_index].erase(range.clone(), style);
+        // Halves of double width characters next to the range may be erased as well.
+        let damaged = range.start.saturating_sub(1)..range.end.saturating_add(1);
+        self.damage_cells(line_index, damaged);
+    }
+
+    /// Damages cells of the screen line, if it is visible in the viewport.
+    fn damage_cells(&mut self, line: usize, cells: Range<usize>) {
+        let line = line + self.viewport_offset;
+        if line < self.size.height() as usize {
+            let cells = cells.start as u32..cells.end.min(u32::MAX as usize) as u32;
+            self.damage.damage_cells(line as u32, cells);
+        }
+    }
+
+    /// Records scroll of the screen lines, see [`RendererAction::Scroll`].
+    fn damage_scroll(&mut self, region: Range<usize>, lines: i32) {
+        // Scrolled viewport is not aligned with the screen, so the lines are damaged instead.
+        if self.viewport_offset > 0 {
+            self.damage_lines(region);
+            return;
         }
-        self.current_cell = Point::new(0, 0);
+        self.damage
+            .scroll(region.start as u32..region.end as u32, lines);
     }
 
-    fn cell_mut(&mut self, cell: Point<CellsUnit>) -> &mut Cell {
+    fn damage_lines(&mut self, lines: Range<usize>) {
+        let width = self.size.width() as usize;
+        for line in lines {
+            self.damage_cells(line, 0..width);
+        }
+    }
+
+    pub fn cell(&self, cell: Point<CellsUnit>) -> &Cell {
         let line_index = cell.vertical_distance() as usize;
-        let line = &mut self.lines[line_index];
+        let line = &self.lines[line_index];
         let cell_index = cell.horizontal_distance() as usize;
-        line.cell_mut(cell_index)
+        line.cell(cell_index)
     }
 
     pub fn current_cell(&self) -> Point<CellsUnit> {
         self.current_cell
     }
+
+    pub fn style_mut(&mut self) -> &mut Style {
+        &mut self.style
+    }
+
+    pub fn size(&self) -> Dimensions<CellsUnit> {
+        self.size
+    }
+
+    /// Changes size of the screens. Lines of the primary screen and scrollback are reflowed to the
+    /// new width, while the alternate screen is only truncated or extended, as programs using it
+    /// redraw it anyway.
+    pub fn resize(&mut self, size: Dimensions<CellsUnit>) {
+        assert!(size.width() >= 1 && size.height() >= 1);
+        log::debug!("Resizing cells from {:?} to {:?}", self.size, size);
+
+        let primary_cursor = if self.alternate_screen {
+            self.inactive_saved_cursor.cell
+        } else {
+            self.current_cell
+        };
+        let mut primary_lines = if self.alternate_screen {
+            mem::take(&mut self.inactive_lines)
+        } else {
+            mem::take(&mut self.lines)
+        };
+        let mut alternate_lines = if self.alternate_screen {
+            mem::take(&mut self.lines)
+        } else {
+            mem::take(&mut self.inactive_lines)
+        };
+
+        let primary_cursor = self.reflow_primary_screen(&mut primary_lines, primary_cursor, size);
+        Self::resize_alternate_screen(&mut alternate_lines, size);
+
+        self.size = size;
+        self.scroll_region = 0..size.height() as usize;
+        self.viewport_offset = 0;
+        self.pending_wrap = false;
+        self.damage.resize(size);
+        if self.alternate_screen {
+            self.lines = alternate_lines;
+            self.inactive_lines = primary_lines;
+            self.inactive_saved_cursor.cell = primary_cursor;
+            self.move_to(self.current_cell);
+            self.saved_cursor.cell = self.clamp(self.saved_cursor.cell);
+        } else {
+            self.lines = primary_lines;
+            self.inactive_lines = alternate_lines;
+            self.current_cell = primary_cursor;
+            self.saved_cursor.cell = self.clamp(self.saved_cursor.cell);
+            self.inactive_saved_cursor.cell = self.clamp(self.inactive_saved_cursor.cell);
+        }
+    }
+
+    fn reflow_primary_screen(
+        &mut self,
+        lines: &mut VecDeque<Line>,
+        cursor: Point<CellsUnit>,
+        size: Dimensions<CellsUnit>,
+    ) -> Point<CellsUnit> {
+        let width = size.width() as usize;
+        let height = size.height() as usize;
+
+        let scrollback_length = self.scrollback.len() as u32;
+        let cursor = cursor.shifted(0, scrollback_length as i32);
+        let all_lines = self.scrollback.drain(..).chain(lines.drain(..));
+        let (mut all_lines, cursor) = reflow::reflow(all_lines, cursor, width);
+
+        let cursor_line = cursor.vertical_distance() as usize;
+        while all_lines.len() > cursor_line + 1 && all_lines.last().is_some_and(Line::is_blank) {
+            all_lines.pop();
+        }
+
+        // Lines above the new screen go to the scrollback, but the cursor has to stay visible.
+        let screen_start = all_lines.len().saturating_sub(height).min(cursor_line);
+        let mut screen_lines = all_lines.split_off(screen_start);
+        screen_lines.truncate(height);
+        screen_lines.resize(height, Line::new(width));
+
+        let scrollback_start = all_lines.len().saturating_sub(self.scrollback_capacity);
+        self.scrollback.extend(all_lines.drain(scrollback_start..));
+        *lines = VecDeque::from(screen_lines);
+
+        cursor.shifted(0, -(screen_start as i32))
+    }
+
+    fn resize_alternate_screen(lines: &mut VecDeque<Line>, size: Dimensions<CellsUnit>) {
+        let width = size.width() as usize;
+        for line in lines.iter_mut() {
+            line.resize(width);
+        }
+        lines.resize(size.height() as usize, Line::new(width));
+    }
+
+    fn clamp(&self, cell: Point<CellsUnit>) -> Point<CellsUnit> {
+        let horizontal_distance = cell.horizontal_distance().min(self.size.width() - 1);
+        let vertical_distance = cell.vertical_distance().min(self.size.height() - 1);
+        Point::new(horizontal_distance, vertical_distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{Alpha, Rgb};
+    use crate::terminal::cells::style::Attributes;
+    use crate::terminal::parser::GraphicRendition;
+
+    #[test]
+    fn test_move_to_clamps_to_size() {
+        let mut cells = Cells::new(Dimensions::new(10, 5), 0);
+        cells.move_to(Point::new(3, 2));
+        assert_eq!((3, 2), distances(cells.current_cell()));
+        cells.move_to(Point::new(20, 20));
+        assert_eq!((9, 4), distances(cells.current_cell()));
+        cells.move_to_column(0);
+        assert_eq!((0, 4), distances(cells.current_cell()));
+        cells.move_to_line(1);
+        assert_eq!((0, 1), distances(cells.current_cell()));
+    }
+
+    #[test]
+    fn test_pending_wrap() {
+        let mut cells = Cells::new(Dimensions::new(3, 2), 0);
+        for character in "abcdef".chars() {
+            cells.push_character(Some(character));
+        }
+        // Writing the bottom right cell does not scroll until another character follows.
+        assert_eq!(vec!["abc", "def"], lines(&cells));
+        assert_eq!((2, 1), distances(cells.current_cell()));
+        assert!(cells.lines[0].is_wrapped());
+        assert!(!cells.lines[1].is_wrapped());
+
+        cells.carriage_return();
+        cells.new_line();
+        assert_eq!(vec!["def", "   "], lines(&cells));
+        assert!(!cells.lines[0].is_wrapped());
+
+        for character in "ghi".chars() {
+            cells.push_character(Some(character));
+        }
+        // Moving the cursor cancels the pending wrap and starts from the last column.
+        cells.move_back();
+        cells.push_character(Some('x'));
+        cells.push_character(Some('y'));
+        assert_eq!(vec!["def", "gxy"], lines(&cells));
+        cells.push_character(Some('z'));
+        assert_eq!(vec!["gxy", "z  "], lines(&cells));
+        assert!(cells.lines[0].is_wrapped());
+    }
+
+    #[test]
+    fn test_erase() {
+        let mut cells = Cells::new(Dimensions::new(4, 3), 0);
+        for _ in 0..11 {
+            cells.push_character(Some('x'));
+        }
+        cells.move_to(Point::new(1, 1));
+        cells.erase_characters(2);
+        assert_eq!(vec!["xxxx", "x  x", "xxx "], lines(&cells));
+        cells.erase_above();
+        assert_eq!(vec!["    ", "   x", "xxx "], lines(&cells));
+        cells.erase_below();
+        assert_eq!(vec!["    ", "    ", "    "], lines(&cells));
+        assert_eq!((1, 1), distances(cells.current_cell()));
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let mut cells = Cells::new(Dimensions::new(4, 3), 0);
+        for character in "abcdefghijk".chars() {
+            cells.push_character(Some(character));
+        }
+        cells.move_to(Point::new(1, 0));
+        cells.insert_characters(2);
+        assert_eq!(vec!["a  b", "efgh", "ijk "], lines(&cells));
+        cells.delete_characters(1);
+        assert_eq!(vec!["a b ", "efgh", "ijk "], lines(&cells));
+
+        cells.set_insert_mode(true);
+        cells.push_character(Some('x'));
+        assert_eq!(vec!["ax b", "efgh", "ijk "], lines(&cells));
+
+        cells.insert_lines(1);
+        assert_eq!(vec!["    ", "ax b", "efgh"], lines(&cells));
+        assert_eq!((0, 0), distances(cells.current_cell()));
+        cells.move_to(Point::new(2, 1));
+        cells.delete_lines(5);
+        assert_eq!(vec!["    ", "    ", "    "], lines(&cells));
+    }
+
+    #[test]
+    fn test_scroll_region() {
+        let mut cells = Cells::new(Dimensions::new(2, 4), 0);
+        for character in "abcdefg".chars() {
+            cells.push_character(Some(character));
+        }
+        cells.set_scroll_region(1, Some(3));
+        cells.move_to(Point::new(0, 2));
+        cells.new_line();
+        assert_eq!(vec!["ab", "ef", "  ", "g "], lines(&cells));
+        assert_eq!((0, 2), distances(cells.current_cell()));
+
+        cells.move_to(Point::new(0, 1));
+        cells.reverse_index();
+        assert_eq!(vec!["ab", "  ", "ef", "g "], lines(&cells));
+        cells.scroll_up(5);
+        assert_eq!(vec!["ab", "  ", "  ", "g "], lines(&cells));
+
+        cells.move_to(Point::new(0, 3));
+        cells.new_line();
+        assert_eq!((0, 3), distances(cells.current_cell()));
+        assert_eq!(vec!["ab", "  ", "  ", "g "], lines(&cells));
+    }
+
+    #[test]
+    fn test_move_within_scroll_region() {
+        let mut cells = Cells::new(Dimensions::new(2, 6), 0);
+        cells.set_scroll_region(1, Some(4));
+        cells.move_to(Point::new(1, 2));
+        cells.move_up_by(5);
+        assert_eq!((1, 1), distances(cells.current_cell()));
+        cells.move_down_by(5);
+        assert_eq!((1, 3), distances(cells.current_cell()));
+
+        // Outside of the region the cursor moves up to the screen edges.
+        cells.move_to(Point::new(0, 0));
+        cells.move_up_by(1);
+        assert_eq!((0, 0), distances(cells.current_cell()));
+        cells.move_to(Point::new(0, 4));
+        cells.move_down_by(5);
+        assert_eq!((0, 5), distances(cells.current_cell()));
+        cells.move_up_by(5);
+        assert_eq!((0, 1), distances(cells.current_cell()));
+    }
+
+    #[test]
+    fn test_alternate_screen() {
+        let mut cells = Cells::new(Dimensions::new(2, 2), 0);
+        cells.push_character(Some('a'));
+        cells.switch_to_alternate_screen();
+        assert_eq!(vec!["  ", "  "], lines(&cells));
+        cells.push_character(Some('b'));
+        assert_eq!(vec![" b", "  "], lines(&cells));
+        cells.switch_to_primary_screen();
+        cells.switch_to_primary_screen();
+        assert_eq!(vec!["a ", "  "], lines(&cells));
+        cells.switch_to_alternate_screen();
+        assert_eq!(vec![" b", "  "], lines(&cells));
+    }
+
+    #[test]
+    fn test_save_and_restore_cursor() {
+        let mut cells = Cells::new(Dimensions::new(4, 4), 0);
+        cells.set_scroll_region(1, Some(3));
+        cells.set_origin_mode(true);
+        assert_eq!((0, 1), distances(cells.current_cell()));
+        cells.move_to_position(Point::new(2, 5));
+        assert_eq!((2, 2), distances(cells.current_cell()));
+        cells
+            .style_mut()
+            .apply(GraphicRendition::SetAttributes(Attributes::BOLD));
+        cells.save_cursor();
+
+        cells.switch_to_alternate_screen();
+        cells.set_origin_mode(false);
+        *cells.style_mut() = Style::new();
+        cells.save_cursor();
+        cells.switch_to_primary_screen();
+
+        cells.move_to(Point::new(0, 0));
+        cells.restore_cursor();
+        assert_eq!((2, 2), distances(cells.current_cell()));
+        assert_eq!(Attributes::BOLD, cells.style_mut().attributes());
+        cells.move_to_position(Point::new(0, 0));
+        assert_eq!((0, 1), distances(cells.current_cell()));
+    }
+
+    #[test]
+    fn test_scrollback() {
+        let mut cells = Cells::new(Dimensions::new(1, 2), 2);
+        for character in "abcde".chars() {
+            cells.push_character(Some(character));
+        }
+        assert_eq!(vec!["d", "e"], lines(&cells));
+        cells.scroll_viewport_up(1);
+        assert_eq!(vec!["c", "d"], lines(&cells));
+        assert_eq!(None, cells.viewport_cursor().map(distances));
+
+        cells.push_character(Some('f'));
+        assert_eq!(vec!["c", "d"], lines(&cells));
+        cells.scroll_viewport_up(5);
+        assert_eq!(vec!["c", "d"], lines(&cells));
+        cells.scroll_viewport_down(1);
+        assert_eq!(vec!["d", "e"], lines(&cells));
+        cells.scroll_viewport_down(1);
+        assert_eq!(vec!["e", "f"], lines(&cells));
+        assert_eq!(Some((0, 1)), cells.viewport_cursor().map(distances));
+
+        cells.switch_to_alternate_screen();
+        for character in "ghi".chars() {
+            cells.push_character(Some(character));
+        }
+        cells.switch_to_primary_screen();
+        cells.scroll_viewport_up(2);
+        assert_eq!(vec!["c", "d"], lines(&cells));
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut cells = Cells::new(Dimensions::new(4, 2), 10);
+        for character in "abcdefg".chars() {
+            cells.push_character(Some(character));
+        }
+        cells.carriage_return();
+        cells.new_line();
+        cells.push_character(Some('h'));
+        assert_eq!(vec!["efg ", "h   "], lines(&cells));
+
+        cells.resize(Dimensions::new(3, 3));
+        assert_eq!(vec!["def", "g  ", "h  "], lines(&cells));
+        assert_eq!((1, 2), distances(cells.current_cell()));
+        cells.scroll_viewport_up(10);
+        assert_eq!(vec!["abc", "def", "g  "], lines(&cells));
+
+        cells.resize(Dimensions::new(8, 2));
+        cells.scroll_viewport_down(10);
+        assert_eq!(vec!["abcdefg ", "h       "], lines(&cells));
+        assert_eq!((1, 1), distances(cells.current_cell()));
+    }
+
+    #[test]
+    fn test_double_width() {
+        let mut cells = Cells::new(Dimensions::new(5, 2), 0);
+        for character in "a中b文".chars() {
+            cells.push_character(Some(character));
+        }
+        assert_eq!(vec!["a中 b ", "文    "], lines(&cells));
+        assert_eq!(CellWidth::Double, cells.cell(Point::new(1, 0)).width());
+        assert_eq!(CellWidth::Spacer, cells.cell(Point::new(2, 0)).width());
+        assert_eq!((2, 1), distances(cells.current_cell()));
+
+        cells.move_to(Point::new(2, 0));
+        cells.push_character(Some('x'));
+        assert_eq!(vec!["a xb ", "文    "], lines(&cells));
+        assert_eq!(CellWidth::Single, cells.cell(Point::new(1, 0)).width());
+
+        cells.move_to(Point::new(1, 1));
+        cells.erase_characters(1);
+        assert_eq!(vec!["a xb ", "     "], lines(&cells));
+    }
+
+    #[test]
+    fn test_combining_marks() {
+        let mut cells = Cells::new(Dimensions::new(3, 3), 0);
+        for character in "e\u{301}a\u{323}\u{302}👨\u{200d}👩".chars() {
+            cells.push_character(Some(character));
+        }
+        assert_eq!(&['\u{301}'], cells.cell(Point::new(0, 0)).marks());
+        assert_eq!(['\u{323}', '\u{302}'], cells.cell(Point::new(1, 0)).marks());
+        assert_eq!(Some('👨'), cells.cell(Point::new(0, 1)).character());
+        assert_eq!(['\u{200d}', '👩'], cells.cell(Point::new(0, 1)).marks());
+        assert_eq!((2, 1), distances(cells.current_cell()));
+
+        // Mark without a preceding character takes a cell of its own.
+        cells.move_to(Point::new(1, 2));
+        cells.push_character(Some('\u{301}'));
+        assert_eq!(Some('\u{301}'), cells.cell(Point::new(1, 2)).character());
+    }
+
+    #[test]
+    fn test_damage() {
+        let mut cells = Cells::new(Dimensions::new(4, 3), 10);
+        cells.take_damage();
+        cells.push_character(Some('a'));
+        cells.push_character(Some('b'));
+        assert_eq!(vec![render_cells(0, 0..2)], cells.take_damage());
+
+        cells.move_to(Point::new(3, 2));
+        cells.erase_characters(1);
+        assert_eq!(
+            vec![render_cells(0, 2..3), render_cells(2, 2..4)],
+            cells.take_damage()
+        );
+
+        cells.new_line();
+        assert_eq!(3, cells.take_damage().len());
+        cells.scroll_viewport_up(1);
+        cells.take_damage();
+        cells.move_to(Point::new(0, 0));
+        cells.push_character(Some('c'));
+        assert_eq!(vec![render_cells(1, 0..1)], cells.take_damage());
+    }
+
+    #[test]
+    fn test_dump() {
+        let palette = DumpPalette {
+            foreground: Rgb::new(255, 255, 255),
+            background: Rgb::new(0, 0, 0),
+            faint_alpha: Alpha::new(128),
+        };
+        let mut cells = Cells::new(Dimensions::new(3, 2), 10);
+        for character in "abcdefg".chars() {
+            cells.push_character(Some(character));
+        }
+        cells.carriage_return();
+        cells.new_line();
+        cells.push_character(Some('h'));
+        assert_eq!("g\nh\n", cells.dump(DumpFormat::Plain, false, palette));
+        assert_eq!("abcdefg\nh\n", cells.dump(DumpFormat::Plain, true, palette));
+
+        cells.switch_to_alternate_screen();
+        cells.push_character(Some('x'));
+        assert_eq!("\n x\n", cells.dump(DumpFormat::Plain, true, palette));
+    }
+
+    fn render_cells(line: u32, cells: Range<u32>) -> RendererAction {
+        RendererAction::RenderCells { line, cells }
+    }
+
+    fn lines(cells: &Cells) -> Vec<String> {
+        (0..cells.size().height())
+            .map(|index| cells.viewport_line(index))
+            .map(|line| {
+                line.iter()
+                    .map(|cell| cell.character().unwrap_or(' '))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn distances(point: Point<CellsUnit>) -> (u32, u32) {
+        (point.horizontal_distance(), point.vertical_distance())
+    }
 }
@@ -0,0 +1,172 @@
+use std::mem;
+
+use crate::spatial::point::Point;
+use crate::spatial::CellsUnit;
+use crate::terminal::cells::line::{Cell, CellWidth, Line};
+
+/// Rewraps lines to the given width, joining lines which were wrapped at the right margin before.
+/// Returns the new lines together with the new position of the cursor, which is given as a point
+/// in the passed lines.
+pub fn reflow(
+    lines: impl IntoIterator<Item = Line>,
+    cursor: Point<CellsUnit>,
+    width: usize,
+) -> (Vec<Line>, Point<CellsUnit>) {
+    let cursor_line = cursor.vertical_distance() as usize;
+    let mut reflowed_lines = Vec::new();
+    let mut new_cursor = Point::new(0, 0);
+    let mut logical_line = Vec::new();
+    let mut cursor_offset = None;
+    for (index, line) in lines.into_iter().enumerate() {
+        // Double width character not fitting at the end of the previous line left an empty cell
+        // there, which is not part of the text, as rewrapping adds it again where needed.
+        let padded = logical_line
+            .last()
+            .is_some_and(|cell: &Cell| cell.character().is_none());
+        if padded && line.cell(0).width() == CellWidth::Double {
+            logical_line.pop();
+        }
+        if index == cursor_line {
+            cursor_offset = Some(logical_line.len() + cursor.horizontal_distance() as usize);
+        }
+
+        let wrapped = line.is_wrapped();
+        logical_line.extend(line.into_cells());
+        if !wrapped {
+            let cells = mem::take(&mut logical_line);
+            let cursor = rewrap(&mut reflowed_lines, cells, cursor_offset.take(), width);
+            new_cursor = cursor.unwrap_or(new_cursor);
+        }
+    }
+    if !logical_line.is_empty() {
+        let cursor = rewrap(&mut reflowed_lines, logical_line, cursor_offset, width);
+        new_cursor = cursor.unwrap_or(new_cursor);
+    }
+
+    (reflowed_lines, new_cursor)
+}
+
+fn rewrap(
+    lines: &mut Vec<Line>,
+    cells: Vec<Cell>,
+    cursor_offset: Option<usize>,
+    width: usize,
+) -> Option<Point<CellsUnit>> {
+    let length = cells
+        .iter()
+        .rposition(|cell| !cell.is_blank())
+        .map_or(0, |index| index + 1);
+    let end = length.max(cursor_offset.map_or(0, |offset| offset + 1));
+    let mut cells = cells.into_iter();
+
+    let mut new_cursor = None;
+    let mut line_cells = Vec::with_capacity(width);
+    for index in 0..end {
+        let cell = cells.next().unwrap_or_else(Cell::new);
+        // Double width character is moved to the next line as a whole instead of being split.
+        let cell_width = match cell.width() {
+            CellWidth::Double if width > 1 => 2,
+            _ => 1,
+        };
+        if line_cells.len() + cell_width > width {
+            line_cells.resize(width, Cell::new());
+            lines.push(Line::from_cells(mem::take(&mut line_cells), true));
+        }
+        if cursor_offset == Some(index) {
+            new_cursor = Some(Point::new(line_cells.len() as u32, lines.len() as u32));
+        }
+        line_cells.push(cell);
+    }
+    line_cells.resize(width, Cell::new());
+    lines.push(Line::from_cells(line_cells, false));
+
+    new_cursor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::cells::style::Style;
+
+    fn line(text: &str, width: usize, wrapped: bool) -> Line {
+        let mut line = Line::new(width);
+        for (index, character) in text.chars().enumerate() {
+            line.write(index, Some(character), CellWidth::Single, Style::new());
+        }
+        line.set_wrapped(wrapped);
+        line
+    }
+
+    fn text(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|cell| cell.character().unwrap_or(' '))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reflow_narrower() {
+        let lines = vec![
+            line("abcd", 4, true),
+            line("ef", 4, false),
+            line("g", 4, false),
+        ];
+        let (lines, cursor) = reflow(lines, Point::new(1, 2), 3);
+        assert_eq!(vec!["abc", "def", "g  "], text(&lines));
+        assert_eq!(
+            vec![true, false, false],
+            lines.iter().map(Line::is_wrapped).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (1, 2),
+            (cursor.horizontal_distance(), cursor.vertical_distance())
+        );
+    }
+
+    #[test]
+    fn test_reflow_wider() {
+        let lines = vec![
+            line("ab", 2, true),
+            line("cd", 2, true),
+            line("e", 2, false),
+        ];
+        let (lines, cursor) = reflow(lines, Point::new(1, 2), 6);
+        assert_eq!(vec!["abcde "], text(&lines));
+        assert_eq!(
+            (5, 0),
+            (cursor.horizontal_distance(), cursor.vertical_distance())
+        );
+    }
+
+    #[test]
+    fn test_reflow_drops_padding_before_double_width_character() {
+        let mut second_line = Line::new(3);
+        second_line.write(0, Some('中'), CellWidth::Double, Style::new());
+        let lines = vec![line("ab", 3, true), second_line];
+        let (lines, _) = reflow(lines, Point::new(0, 0), 6);
+        assert_eq!(vec!["ab中   "], text(&lines));
+
+        let (lines, _) = reflow(lines, Point::new(0, 0), 3);
+        assert_eq!(vec!["ab ", "中  "], text(&lines));
+        assert!(lines[0].is_wrapped());
+    }
+
+    #[test]
+    fn test_reflow_keeps_double_width_characters_whole() {
+        let mut first_line = line("ab", 4, true);
+        first_line.write(2, Some('中'), CellWidth::Double, Style::new());
+        let lines = vec![first_line, line("c", 4, false)];
+        let (lines, cursor) = reflow(lines, Point::new(0, 1), 3);
+        assert_eq!(vec!["ab ", "中 c"], text(&lines));
+        assert_eq!(
+            (2, 1),
+            (cursor.horizontal_distance(), cursor.vertical_distance())
+        );
+        assert_eq!(CellWidth::Double, lines[1].cell(0).width());
+        assert_eq!(CellWidth::Spacer, lines[1].cell(1).width());
+    }
+}
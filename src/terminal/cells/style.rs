@@ -0,0 +1,184 @@
+use crate::color::Rgb;
+use crate::terminal::parser::GraphicRendition;
+
+const BASE_COLORS: [Rgb; 16] = [
+    Rgb::new(0, 0, 0),
+    Rgb::new(205, 0, 0),
+    Rgb::new(0, 205, 0),
+    Rgb::new(205, 205, 0),
+    Rgb::new(0, 0, 238),
+    Rgb::new(205, 0, 205),
+    Rgb::new(0, 205, 205),
+    Rgb::new(229, 229, 229),
+    Rgb::new(127, 127, 127),
+    Rgb::new(255, 0, 0),
+    Rgb::new(0, 255, 0),
+    Rgb::new(255, 255, 0),
+    Rgb::new(92, 92, 255),
+    Rgb::new(255, 0, 255),
+    Rgb::new(0, 255, 255),
+    Rgb::new(255, 255, 255),
+];
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Color {
+    Default,
+    Indexed(u8),
+    Rgb(Rgb),
+}
+
+impl Color {
+    pub fn to_rgb(self, default: Rgb) -> Rgb {
+        match self {
+            Color::Default => default,
+            Color::Indexed(index) => Self::indexed_to_rgb(index),
+            Color::Rgb(rgb) => rgb,
+        }
+    }
+
+    fn indexed_to_rgb(index: u8) -> Rgb {
+        match index {
+            0..=15 => BASE_COLORS[index as usize],
+            16..=231 => {
+                let index = index - 16;
+                let red = CUBE_LEVELS[(index / 36) as usize];
+                let green = CUBE_LEVELS[(index / 6 % 6) as usize];
+                let blue = CUBE_LEVELS[(index % 6) as usize];
+                Rgb::new(red, green, blue)
+            }
+            232..=255 => {
+                let level = 8 + 10 * (index - 232);
+                Rgb::new(level, level, level)
+            }
+        }
+    }
+}
+
+/// Set of text attributes packed into bits.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Attributes(u16);
+
+impl Attributes {
+    pub const NONE: Self = Self(0);
+    pub const BOLD: Self = Self(1 << 0);
+    pub const FAINT: Self = Self(1 << 1);
+    pub const ITALIC: Self = Self(1 << 2);
+    pub const UNDERLINE: Self = Self(1 << 3);
+    pub const DOUBLE_UNDERLINE: Self = Self(1 << 4);
+    pub const CURLY_UNDERLINE: Self = Self(1 << 5);
+    pub const BLINK: Self = Self(1 << 6);
+    pub const INVERSE: Self = Self(1 << 7);
+    pub const INVISIBLE: Self = Self(1 << 8);
+    pub const STRIKETHROUGH: Self = Self(1 << 9);
+
+    pub const ALL_UNDERLINES: Self =
+        Self(Self::UNDERLINE.0 | Self::DOUBLE_UNDERLINE.0 | Self::CURLY_UNDERLINE.0);
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersects(&self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Style {
+    foreground: Color,
+    background: Color,
+    attributes: Attributes,
+}
+
+impl Style {
+    pub const fn new() -> Self {
+        Self {
+            foreground: Color::Default,
+            background: Color::Default,
+            attributes: Attributes::NONE,
+        }
+    }
+
+    pub fn apply(&mut self, rendition: GraphicRendition) {
+        match rendition {
+            GraphicRendition::Reset => *self = Self::new(),
+            GraphicRendition::Foreground(color) => self.foreground = color,
+            GraphicRendition::Background(color) => self.background = color,
+            GraphicRendition::SetAttributes(attributes) => {
+                // Underline styles are mutually exclusive, so a new one replaces the previous.
+                if attributes.intersects(Attributes::ALL_UNDERLINES) {
+                    self.attributes.remove(Attributes::ALL_UNDERLINES);
+                }
+                self.attributes.insert(attributes);
+            }
+            GraphicRendition::ResetAttributes(attributes) => self.attributes.remove(attributes),
+        }
+    }
+
+    /// Returns style of erased cells, which keep only the background color.
+    pub fn erased(&self) -> Self {
+        Self {
+            background: self.background,
+            ..Self::new()
+        }
+    }
+
+    pub fn foreground(&self) -> Color {
+        self.foreground
+    }
+
+    pub fn background(&self) -> Color {
+        self.background
+    }
+
+    pub fn attributes(&self) -> Attributes {
+        self.attributes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexed_to_rgb() {
+        let default = Rgb::new(1, 2, 3);
+        assert_eq!(default, Color::Default.to_rgb(default));
+        assert_eq!(BASE_COLORS[9], Color::Indexed(9).to_rgb(default));
+        assert_eq!(Rgb::new(0, 0, 0), Color::Indexed(16).to_rgb(default));
+        assert_eq!(Rgb::new(255, 135, 0), Color::Indexed(208).to_rgb(default));
+        assert_eq!(Rgb::new(255, 255, 255), Color::Indexed(231).to_rgb(default));
+        assert_eq!(Rgb::new(8, 8, 8), Color::Indexed(232).to_rgb(default));
+        assert_eq!(Rgb::new(238, 238, 238), Color::Indexed(255).to_rgb(default));
+    }
+
+    #[test]
+    fn test_apply_attributes() {
+        let mut style = Style::new();
+        style.apply(GraphicRendition::SetAttributes(Attributes::BOLD));
+        style.apply(GraphicRendition::SetAttributes(Attributes::UNDERLINE));
+        style.apply(GraphicRendition::SetAttributes(Attributes::CURLY_UNDERLINE));
+        assert_eq!(
+            Attributes::BOLD.union(Attributes::CURLY_UNDERLINE),
+            style.attributes()
+        );
+
+        style.apply(GraphicRendition::ResetAttributes(
+            Attributes::ALL_UNDERLINES,
+        ));
+        assert_eq!(Attributes::BOLD, style.attributes());
+    }
+}
@@ -1,8 +1,11 @@
+use std::os::unix::io::{AsRawFd, RawFd};
+
 use anyhow::Result;
 use nix::sys::epoll;
 use nix::sys::epoll::{EpollEvent, EpollFlags, EpollOp};
+use nix::sys::signal::{SigSet, Signal};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
 use nix::unistd;
-use std::os::unix::io::RawFd;
 
 #[derive(Debug)]
 pub struct Events {
@@ -21,6 +24,15 @@ impl Events {
         Ok(())
     }
 
+    /// Enables or disables waiting for the registered fd to become writable.
+    pub fn set_write_event(&mut self, fd: RawFd, enabled: bool) -> Result<()> {
+        let mut flags = EpollFlags::EPOLLIN;
+        flags.set(EpollFlags::EPOLLOUT, enabled);
+        let mut event = EpollEvent::new(flags, fd as u64);
+        epoll::epoll_ctl(self.epoll, EpollOp::EpollCtlMod, fd, Some(&mut event))?;
+        Ok(())
+    }
+
     pub fn unregister_event(&mut self, fd: RawFd) -> Result<()> {
         epoll::epoll_ctl(self.epoll, EpollOp::EpollCtlDel, fd, None)?;
         Ok(())
@@ -36,3 +48,32 @@ impl Events {
         Ok(())
     }
 }
+
+/// Receives SIGWINCH, which is sent when the console is resized, e.g. after changing the
+/// framebuffer mode.
+#[derive(Debug)]
+pub struct ResizeSignal {
+    signal_fd: SignalFd,
+}
+
+impl ResizeSignal {
+    /// Blocks SIGWINCH to receive it through the file descriptor. Processes spawned afterwards
+    /// inherit the blocked signal, so it has to be created after spawning the shell.
+    pub fn new() -> Result<Self> {
+        let mut mask = SigSet::empty();
+        mask.add(Signal::SIGWINCH);
+        mask.thread_block()?;
+        let signal_fd = SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK)?;
+        Ok(Self { signal_fd })
+    }
+
+    /// Consumes all pending signals.
+    pub fn read(&mut self) -> Result<()> {
+        while self.signal_fd.read_signal()?.is_some() {}
+        Ok(())
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.signal_fd.as_raw_fd()
+    }
+}
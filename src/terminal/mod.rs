@@ -1,25 +1,34 @@
+use std::mem;
 use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use nix::errno::Errno;
+use nix::poll;
+use nix::poll::{PollFd, PollFlags};
 use nix::sys::epoll::{EpollEvent, EpollFlags};
 use nix::sys::wait;
 use nix::sys::wait::{Id, WaitPidFlag};
 use nix::unistd;
 
-use crate::color::Rgb;
+use crate::color::{Alpha, Rgb};
+use crate::display::screenshot::ScreenshotSettings;
 use crate::display::Display;
 use crate::font::FontRenderer;
+use crate::input::key::{TerminalKey, ViewportKey};
 use crate::input::InputTerminal;
 use crate::spatial::point::Point;
-use crate::terminal::cells::line::RendererAction;
+use crate::terminal::cells::damage::RendererAction;
+use crate::terminal::cells::dump::{DumpFormat, DumpPalette, DumpSettings};
 use crate::terminal::cells::Cells;
-use crate::terminal::event::Events;
-use crate::terminal::parser::{Parser, ParserAction};
+use crate::terminal::event::{Events, ResizeSignal};
+use crate::terminal::parser::{
+    AlternateScreenMode, DisplayErasure, LineErasure, Parser, ParserAction,
+};
 use crate::terminal::renderer::TerminalRenderer;
 use crate::terminal::shell::Shell;
 
-mod cells;
+pub mod cells;
 mod event;
 mod parser;
 pub mod renderer;
@@ -28,40 +37,70 @@ mod shell;
 const BLOCK_CHARACTER: char = '█';
 const BACKGROUND_COLOR: Rgb = Rgb::new(32, 32, 32);
 const FONT_COLOR: Rgb = Rgb::new(249, 250, 244);
+const FAINT_ALPHA: Alpha = Alpha::new(160);
+/// Colors of the HTML dumps, matching those on the display.
+const DUMP_PALETTE: DumpPalette = DumpPalette {
+    foreground: FONT_COLOR,
+    background: BACKGROUND_COLOR,
+    faint_alpha: FAINT_ALPHA,
+};
+
+/// Reported as VT220 with ANSI colors.
+const PRIMARY_DEVICE_ATTRIBUTES: &[u8] = b"\x1b[?62;22c";
+/// Reported as VT220 with firmware version 1 and no ROM cartridge.
+const SECONDARY_DEVICE_ATTRIBUTES: &[u8] = b"\x1b[>1;1;0c";
+const DEVICE_STATUS_OK: &[u8] = b"\x1b[0n";
+/// Progressive keyboard enhancements are not supported, so no flags are reported.
+const KEYBOARD_FLAGS: &[u8] = b"\x1b[?0u";
+/// Shortest time between text dumps requested by programs, so they cannot flood the disk.
+const DUMP_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub struct Terminal {
-    input: InputTerminal,
     shell: Shell,
     renderer: TerminalRenderer,
     cells: Cells,
     events: Events,
     parser: Parser,
+    /// Actions of the parser, kept between reads to reuse the allocation.
+    actions: Vec<ParserAction>,
+    /// Whether the event loop waits for the shell to accept pending input.
+    shell_write_event: bool,
+    screenshot_settings: ScreenshotSettings,
+    dump_settings: DumpSettings,
+    last_dump_request: Option<Instant>,
 }
 
 impl Terminal {
     pub fn new(
-        input: InputTerminal,
-        display: Display,
+        display: Box<dyn Display>,
         font: FontRenderer,
         shell_path: &str,
+        scrollback_lines: usize,
+        screenshot_settings: ScreenshotSettings,
+        dump_settings: DumpSettings,
     ) -> Result<Self> {
         let display_size = display.size();
         let cell_size = font.character_size(BLOCK_CHARACTER);
         let size = display_size.fit_cells(cell_size);
-        let shell = Shell::spawn(size, shell_path, &[display.device_fd()])?;
+        let fds_to_close: Vec<_> = display.device_fd().into_iter().collect();
+        let shell = Shell::spawn(size, shell_path, &fds_to_close)?;
         let renderer = TerminalRenderer::new(display, font, size, cell_size);
-        let cells = Cells::new(size);
+        let cells = Cells::new(size, scrollback_lines);
         let events = Events::new()?;
         let parser = Parser::new();
 
         Ok(Self {
-            input,
             renderer,
             cells,
             events,
             shell,
             parser,
+            actions: Vec::new(),
+            shell_write_event: false,
+            screenshot_settings,
+            dump_settings,
+            last_dump_request: None,
         })
     }
 
@@ -69,17 +108,21 @@ impl Terminal {
         self.renderer.fill_all(BACKGROUND_COLOR);
         self.push_string("ft 0.1.0");
         self.cells.carriage_return();
-        let action = self.cells.new_line();
-        if let Some(RendererAction::RenderAll) = action {
-            self.render_all();
-        }
+        self.cells.new_line();
+        self.render_screen()?;
 
         self.events.register_read_event(self.shell.master_fd())?;
         self.events
             .register_read_event(InputTerminal::TERMINAL_FD)?;
         self.events.register_read_event(self.shell.pid_fd())?;
+        let mut resize_signal = ResizeSignal::new()?;
+        self.events.register_read_event(resize_signal.fd())?;
+        let display_event_fd = self.renderer.display_event_fd();
+        if let Some(fd) = display_event_fd {
+            self.events.register_read_event(fd)?;
+        }
 
-        let mut events = [EpollEvent::empty(); 4];
+        let mut events = [EpollEvent::empty(); 5];
         let mut bytes = [0; 4096];
 
         log::debug!("Entering main loop");
@@ -92,9 +135,12 @@ impl Terminal {
                 let source = event.data() as RawFd;
                 let flags = event.events();
 
-                if flags == EpollFlags::EPOLLHUP && source == self.shell.master_fd() {
+                let shell_hung_up =
+                    flags.contains(EpollFlags::EPOLLHUP) && !flags.contains(EpollFlags::EPOLLIN);
+                if shell_hung_up && source == self.shell.master_fd() {
                     self.events.unregister_event(self.shell.master_fd())?;
                     unistd::close(self.shell.master_fd())?;
+                    self.shell_write_event = false;
                     log::debug!("Shell closed");
                     continue;
                 }
@@ -105,10 +151,34 @@ impl Terminal {
                     let status = wait::waitid(Id::PIDFd(pid_fd), WaitPidFlag::WEXITED)?;
                     log::info!("Shell exit status: {:?}", status);
                     unistd::close(pid_fd)?;
+                    if self.screenshot_settings.on_exit {
+                        self.drain_shell_output()?;
+                        let path = self.renderer.save_screenshot(&self.screenshot_settings)?;
+                        log::info!("Screenshot saved to {:?}", path);
+                    }
                     self.finish()?;
                     return Ok(());
                 }
 
+                if source == resize_signal.fd() {
+                    resize_signal.read()?;
+                    self.resize()?;
+                    continue;
+                }
+
+                if Some(source) == display_event_fd {
+                    self.renderer.handle_display_events()?;
+                    continue;
+                }
+
+                if source == self.shell.master_fd() && flags.contains(EpollFlags::EPOLLOUT) {
+                    let pending = self.shell.flush();
+                    self.set_shell_write_event(pending);
+                    if !flags.contains(EpollFlags::EPOLLIN) {
+                        continue;
+                    }
+                }
+
                 let bytes_read = unistd::read(source, &mut bytes)?;
                 let bytes = &bytes[0..bytes_read];
                 log::debug!(
@@ -120,16 +190,7 @@ impl Terminal {
                 );
 
                 if source == InputTerminal::TERMINAL_FD {
-                    let result = unistd::write(self.shell.master_fd(), bytes);
-                    match result {
-                        Err(Errno::EBADF) => {
-                            log::warn!("Cannot write to master fd");
-                        }
-                        Err(_) => {
-                            result?;
-                        }
-                        _ => {}
-                    }
+                    self.handle_input(bytes)?;
                     continue;
                 }
 
@@ -139,12 +200,12 @@ impl Terminal {
     }
 
     fn handle_bytes(&mut self, bytes: &[u8]) -> Result<()> {
-        for byte in bytes {
-            let byte = *byte;
-            let action = self.parser.push_byte(byte)?;
+        let mut actions = mem::take(&mut self.actions);
+        self.parser.push_bytes(bytes, &mut actions);
+        for action in actions.drain(..) {
             match action {
                 ParserAction::InsertCharacter(character) => {
-                    self.push_character(Some(character));
+                    self.cells.push_character(Some(character));
                 }
                 ParserAction::CarriageReturn => {
                     self.cells.carriage_return();
@@ -153,31 +214,50 @@ impl Terminal {
                     self.cells.new_line();
                 }
                 ParserAction::MoveCursorUp(up) => {
-                    for _ in 0..up {
-                        self.cells.move_up();
-                    }
+                    self.cells.move_up_by(up);
                 }
                 ParserAction::MoveCursorDown(down) => {
-                    for _ in 0..down {
-                        self.cells.new_line();
-                    }
+                    self.cells.move_down_by(down);
                 }
                 ParserAction::MoveCursorForward(forward) => {
-                    for _ in 0..forward {
-                        self.push_character(None);
-                    }
+                    let current_cell = self.cells.current_cell();
+                    self.cells
+                        .move_to_column(current_cell.horizontal_distance().saturating_add(forward));
                 }
                 ParserAction::MoveCursorBack(back) => {
+                    // Moving back wraps to the previous line, so it never crosses more cells than
+                    // the screen has.
+                    let size = self.cells.size();
+                    let back = back.min(size.width() * size.height());
                     for _ in 0..back {
                         self.cells.move_back();
                     }
                 }
                 ParserAction::MoveCursorToNextMultipleOf(multiple) => {
                     let horizontal_distance = self.cells.current_cell().horizontal_distance();
-                    let forward = horizontal_distance % multiple;
-                    for _ in 0..forward {
-                        self.push_character(None);
-                    }
+                    let forward = multiple - horizontal_distance % multiple;
+                    self.cells.move_to_column(horizontal_distance + forward);
+                }
+                ParserAction::MoveCursorTo(position) => {
+                    self.cells.move_to_position(position);
+                }
+                ParserAction::MoveCursorToColumn(column) => {
+                    self.cells.move_to_column(column);
+                }
+                ParserAction::MoveCursorToLine(line) => {
+                    let current_cell = self.cells.current_cell();
+                    let position = Point::new(current_cell.horizontal_distance(), line);
+                    self.cells.move_to_position(position);
+                }
+                ParserAction::MoveCursorToNextLine(down) => {
+                    let current_cell = self.cells.current_cell();
+                    let line = current_cell.vertical_distance().saturating_add(down);
+                    self.cells.move_to(Point::new(0, line));
+                }
+                ParserAction::MoveCursorToPreviousLine(up) => {
+                    let current_cell = self.cells.current_cell();
+                    let line = current_cell.vertical_distance().saturating_sub(up);
+                    self.cells.move_to(Point::new(0, line));
                 }
                 ParserAction::EnableBracketedPasteMode => {
                     log::warn!("Unsupported sequence EnableBracketedPasteMode");
@@ -185,9 +265,129 @@ impl Terminal {
                 ParserAction::DisableBracketedPasteMode => {
                     log::warn!("Unsupported sequence DisableBracketedPasteMode");
                 }
-                ParserAction::Clear => {
-                    self.cells.clear();
-                    self.render_all();
+                ParserAction::EnableAlternateScreen(mode) => {
+                    if mode == AlternateScreenMode::SaveCursor {
+                        self.cells.save_cursor();
+                    }
+                    self.cells.switch_to_alternate_screen();
+                    if mode == AlternateScreenMode::SaveCursor {
+                        self.cells.erase_all();
+                    }
+                }
+                ParserAction::DisableAlternateScreen(mode) => {
+                    if mode == AlternateScreenMode::ClearOnExit && self.cells.is_alternate_screen()
+                    {
+                        self.cells.erase_all();
+                    }
+                    self.cells.switch_to_primary_screen();
+                    if mode == AlternateScreenMode::SaveCursor {
+                        self.cells.restore_cursor();
+                    }
+                }
+                ParserAction::SelectGraphicRendition(renditions) => {
+                    let style = self.cells.style_mut();
+                    for rendition in renditions {
+                        style.apply(rendition);
+                    }
+                }
+                ParserAction::EraseInDisplay(erasure) => match erasure {
+                    DisplayErasure::Below => self.cells.erase_below(),
+                    DisplayErasure::Above => self.cells.erase_above(),
+                    DisplayErasure::All => self.cells.erase_all(),
+                    DisplayErasure::Scrollback => self.cells.clear_scrollback(),
+                },
+                ParserAction::EraseInLine(erasure) => match erasure {
+                    LineErasure::Right => self.cells.erase_line_right(),
+                    LineErasure::Left => self.cells.erase_line_left(),
+                    LineErasure::All => self.cells.erase_line(),
+                },
+                ParserAction::EraseCharacters(count) => {
+                    self.cells.erase_characters(count);
+                }
+                ParserAction::InsertCharacters(count) => {
+                    self.cells.insert_characters(count);
+                }
+                ParserAction::DeleteCharacters(count) => {
+                    self.cells.delete_characters(count);
+                }
+                ParserAction::InsertLines(count) => {
+                    self.cells.insert_lines(count);
+                }
+                ParserAction::DeleteLines(count) => {
+                    self.cells.delete_lines(count);
+                }
+                ParserAction::SetScrollRegion { top, bottom } => {
+                    self.cells.set_scroll_region(top, bottom);
+                }
+                ParserAction::ScrollUp(count) => {
+                    self.cells.scroll_up(count);
+                }
+                ParserAction::ScrollDown(count) => {
+                    self.cells.scroll_down(count);
+                }
+                ParserAction::ReverseIndex => {
+                    self.cells.reverse_index();
+                }
+                ParserAction::NextLine => {
+                    self.cells.carriage_return();
+                    self.cells.new_line();
+                }
+                ParserAction::SaveCursor => {
+                    self.cells.save_cursor();
+                }
+                ParserAction::RestoreCursor => {
+                    self.cells.restore_cursor();
+                }
+                ParserAction::DesignateCharset(slot, charset) => {
+                    self.cells.designate_charset(slot, charset);
+                }
+                ParserAction::ShiftCharset(slot) => {
+                    self.cells.shift_charset(slot);
+                }
+                ParserAction::EnableOriginMode => {
+                    self.cells.set_origin_mode(true);
+                }
+                ParserAction::DisableOriginMode => {
+                    self.cells.set_origin_mode(false);
+                }
+                ParserAction::ReportDeviceStatus => {
+                    self.write_to_shell(DEVICE_STATUS_OK);
+                }
+                ParserAction::ReportCursorPosition => {
+                    let position = self.cells.cursor_position();
+                    let report = format!(
+                        "\x1b[{};{}R",
+                        position.vertical_distance() + 1,
+                        position.horizontal_distance() + 1
+                    );
+                    self.write_to_shell(report.as_bytes());
+                }
+                ParserAction::ReportExtendedCursorPosition => {
+                    let position = self.cells.cursor_position();
+                    let report = format!(
+                        "\x1b[?{};{}R",
+                        position.vertical_distance() + 1,
+                        position.horizontal_distance() + 1
+                    );
+                    self.write_to_shell(report.as_bytes());
+                }
+                ParserAction::ReportPrimaryDeviceAttributes => {
+                    self.write_to_shell(PRIMARY_DEVICE_ATTRIBUTES);
+                }
+                ParserAction::ReportSecondaryDeviceAttributes => {
+                    self.write_to_shell(SECONDARY_DEVICE_ATTRIBUTES);
+                }
+                ParserAction::ReportKeyboardFlags => {
+                    self.write_to_shell(KEYBOARD_FLAGS);
+                }
+                ParserAction::DumpScreen(format) => {
+                    self.handle_dump_request(format);
+                }
+                ParserAction::EnableInsertMode => {
+                    self.cells.set_insert_mode(true);
+                }
+                ParserAction::DisableInsertMode => {
+                    self.cells.set_insert_mode(false);
                 }
                 ParserAction::MoreBytes => {}
                 ParserAction::Ignore => {}
@@ -196,50 +396,452 @@ impl Terminal {
                 }
             }
         }
+        self.actions = actions;
 
-        self.render_all();
-        let current_cell = self.cells.current_cell();
-        self.renderer.fill_cell(current_cell, FONT_COLOR);
+        self.render_screen()?;
+        Ok(())
+    }
 
+    fn handle_input(&mut self, mut bytes: &[u8]) -> Result<()> {
+        let mut forwarded_bytes = Vec::with_capacity(bytes.len());
+        let mut viewport_changed = false;
+        while let Some(byte) = bytes.first() {
+            if let Some((key, length)) = TerminalKey::parse(bytes) {
+                match key {
+                    TerminalKey::Viewport(key) => {
+                        self.scroll_viewport(key);
+                        viewport_changed = true;
+                    }
+                    TerminalKey::Screenshot => self.save_screenshot(),
+                    TerminalKey::Dump => self.save_dump(self.dump_settings.format),
+                }
+                bytes = &bytes[length..];
+            } else {
+                forwarded_bytes.push(*byte);
+                bytes = &bytes[1..];
+            }
+        }
+
+        if !forwarded_bytes.is_empty() {
+            if self.cells.is_viewport_scrolled() {
+                self.cells.scroll_viewport_to_bottom();
+                viewport_changed = true;
+            }
+            self.write_to_shell(&forwarded_bytes);
+        }
+        if viewport_changed {
+            self.render_screen()?;
+        }
         Ok(())
     }
 
-    fn push_string(&mut self, string: &str) {
-        for character in string.chars() {
-            self.push_character(Some(character));
+    fn scroll_viewport(&mut self, key: ViewportKey) {
+        let page = self.cells.size().height() as usize;
+        match key {
+            ViewportKey::PageUp => self.cells.scroll_viewport_up(page),
+            ViewportKey::PageDown => self.cells.scroll_viewport_down(page),
+            ViewportKey::Top => self.cells.scroll_viewport_up(usize::MAX),
+            ViewportKey::Bottom => self.cells.scroll_viewport_to_bottom(),
         }
     }
 
-    fn push_character(&mut self, character: Option<char>) {
-        let action = self.cells.push_character(character);
-        match action {
-            RendererAction::RenderAll => self.render_all(),
-            RendererAction::RenderCell(cell) => {
-                self.renderer.fill_cell(cell, BACKGROUND_COLOR);
-                let Some(character) = character else { return; };
-                self.renderer
-                    .render_character(character, cell, FONT_COLOR, BACKGROUND_COLOR);
+    /// Saves screenshot of the display. Failure is only logged, as it should not end the terminal.
+    fn save_screenshot(&self) {
+        match self.renderer.save_screenshot(&self.screenshot_settings) {
+            Ok(path) => log::info!("Screenshot saved to {:?}", path),
+            Err(error) => log::warn!("Saving screenshot failed: {:#?}", error),
+        }
+    }
+
+    /// Saves text of the cells in the given format. Failure is only logged, as it should not end
+    /// the terminal.
+    fn save_dump(&self, format: DumpFormat) {
+        match self.dump_settings.save(&self.cells, format, DUMP_PALETTE) {
+            Ok(path) => log::info!("Text dump saved to {:?}", path),
+            Err(error) => log::warn!("Saving text dump failed: {:#?}", error),
+        }
+    }
+
+    /// Saves text dump requested by a program, if requests are enabled and the previous one is not
+    /// too recent.
+    fn handle_dump_request(&mut self, format: Option<DumpFormat>) {
+        if !self.dump_settings.requests {
+            log::debug!("Ignoring text dump request, as requests are disabled");
+            return;
+        }
+        let now = Instant::now();
+        let too_soon = self
+            .last_dump_request
+            .is_some_and(|last| now.duration_since(last) < DUMP_REQUEST_INTERVAL);
+        if too_soon {
+            log::debug!("Ignoring text dump request, which came too soon after the previous one");
+            return;
+        }
+        self.last_dump_request = Some(now);
+        self.save_dump(format.unwrap_or(self.dump_settings.format));
+    }
+
+    /// Handles output written by the shell before it exited, which may be still waiting in the
+    /// pseudoterminal.
+    fn drain_shell_output(&mut self) -> Result<()> {
+        let master_fd = self.shell.master_fd();
+        let mut bytes = [0; 4096];
+        loop {
+            let mut poll_fds = [PollFd::new(master_fd, PollFlags::POLLIN)];
+            poll::poll(&mut poll_fds, 0)?;
+            let readable = poll_fds[0]
+                .revents()
+                .is_some_and(|events| events.contains(PollFlags::POLLIN));
+            if !readable {
+                return Ok(());
+            }
+            match unistd::read(master_fd, &mut bytes) {
+                Ok(0) | Err(Errno::EIO) => return Ok(()),
+                Ok(bytes_read) => self.handle_bytes(&bytes[..bytes_read])?,
+                Err(error) => return Err(error.into()),
             }
         }
     }
 
-    fn render_all(&mut self) {
+    fn resize(&mut self) -> Result<()> {
+        let size = self.renderer.reload_display()?;
+        if size.width() == 0 || size.height() == 0 {
+            log::warn!("Display is too small to fit any cell: {:?}", size);
+            return Ok(());
+        }
+
+        self.cells.resize(size);
+        self.renderer.set_size(size);
+        self.shell.resize(size)?;
+        // Cells may not cover the whole display, so the margins are cleared as well.
         self.renderer.fill_all(BACKGROUND_COLOR);
+        self.render_screen()?;
+        Ok(())
+    }
+
+    /// Renders cells changed since the last render and the cursor.
+    fn render_screen(&mut self) -> Result<()> {
+        for action in self.cells.take_damage() {
+            self.render(action);
+        }
+        if let Some(cursor) = self.cells.viewport_cursor() {
+            self.renderer.fill_cell(cursor, FONT_COLOR);
+        }
+        self.renderer.flush()
+    }
+
+    /// Writes the bytes to the shell, or queues them until it reads its input, so the terminal
+    /// never blocks on it.
+    fn write_to_shell(&mut self, bytes: &[u8]) {
+        let pending = self.shell.write(bytes);
+        self.set_shell_write_event(pending);
+    }
 
-        for (index, line) in self.cells.iter().enumerate() {
-            for (character_index, character) in line.iter().enumerate() {
-                let character = character.character();
-                let Some(character) = character else { continue; };
-                let cell = Point::new(character_index as u32, index as u32);
-                self.renderer
-                    .render_character(character, cell, FONT_COLOR, BACKGROUND_COLOR);
+    fn set_shell_write_event(&mut self, enabled: bool) {
+        if enabled == self.shell_write_event {
+            return;
+        }
+        match self.events.set_write_event(self.shell.master_fd(), enabled) {
+            Ok(()) => self.shell_write_event = enabled,
+            Err(error) => log::warn!("Waiting for shell input failed: {:#?}", error),
+        }
+    }
+
+    fn push_string(&mut self, string: &str) {
+        for character in string.chars() {
+            self.cells.push_character(Some(character));
+        }
+    }
+
+    fn render(&mut self, action: RendererAction) {
+        match action {
+            RendererAction::RenderCells { line, cells } => {
+                let viewport_line = self.cells.viewport_line(line);
+                let damaged_cells = viewport_line
+                    .iter()
+                    .enumerate()
+                    .take(cells.end as usize)
+                    .skip(cells.start as usize);
+                for (cell_index, cell) in damaged_cells {
+                    let point = Point::new(cell_index as u32, line);
+                    self.renderer.render_cell(cell, point);
+                }
+            }
+            RendererAction::Scroll { region, lines } => {
+                self.renderer.scroll(region, lines);
             }
         }
     }
 
     fn finish(self) -> Result<()> {
-        self.input.finish()?;
         self.events.finish()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, ptr};
+
+    use nix::libc;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::display::memory::{MemoryDisplay, MemoryFrame};
+    use crate::display::screenshot::ScreenshotFormat;
+    use crate::spatial::dimension::Dimensions;
+    use crate::spatial::rectangle::Rectangle;
+    use crate::spatial::{CellsUnit, PixelsUnit};
+
+    /// DejaVu Sans Mono reduced to Latin-1 and the few other characters the tests render.
+    const FONT: &[u8] = include_bytes!("../../tests/fixtures/DejaVuSansMono-subset.ttf");
+    const RED: Rgb = Rgb::new(255, 0, 0);
+
+    struct TestTerminal {
+        terminal: Terminal,
+        frame: MemoryFrame,
+        cell_size: Dimensions<PixelsUnit>,
+    }
+
+    impl TestTerminal {
+        /// Creates terminal rendering to memory, with `cat` as the shell, so replies written to
+        /// it go nowhere.
+        fn new(width: u32, height: u32) -> Self {
+            let font = FontRenderer::from_bytes(16, FONT.to_vec()).unwrap();
+            let cell_size = font.character_size(BLOCK_CHARACTER);
+            let display_size =
+                Dimensions::new(width * cell_size.width(), height * cell_size.height());
+            let display = MemoryDisplay::new(display_size);
+            let frame = display.frame();
+            let screenshot_settings = ScreenshotSettings {
+                directory: env::temp_dir(),
+                format: ScreenshotFormat::Png,
+                on_exit: false,
+            };
+            let dump_settings = DumpSettings {
+                directory: env::temp_dir(),
+                format: DumpFormat::Plain,
+                scrollback: false,
+                requests: false,
+            };
+            let mut terminal = Terminal::new(
+                Box::new(display),
+                font,
+                "/bin/cat",
+                10,
+                screenshot_settings,
+                dump_settings,
+            )
+            .unwrap();
+            terminal.renderer.fill_all(BACKGROUND_COLOR);
+            terminal.render_screen().unwrap();
+            Self {
+                terminal,
+                frame,
+                cell_size,
+            }
+        }
+
+        fn push_bytes(&mut self, bytes: &[u8]) {
+            self.terminal.handle_bytes(bytes).unwrap();
+        }
+
+        fn cell_color(&self, column: u32, line: u32) -> Option<Rgb> {
+            let cell = Point::<CellsUnit>::new(column, line);
+            let rectangle = Rectangle::new(cell.to_pixels(self.cell_size), self.cell_size);
+            let rgb = self.frame.rgb(rectangle.origin());
+            self.frame.is_filled(rectangle, rgb).then_some(rgb)
+        }
+    }
+
+    impl Drop for TestTerminal {
+        /// Kills and reaps `cat` and closes the pseudoterminal, so tests do not leak them.
+        fn drop(&mut self) {
+            let shell = &self.terminal.shell;
+            // SAFETY: Pid fd refers to the child, which is not reaped until below, and signal
+            // information may be null.
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_pidfd_send_signal,
+                    shell.pid_fd(),
+                    libc::SIGKILL,
+                    ptr::null::<libc::siginfo_t>(),
+                    0,
+                )
+            };
+            if result == 0 {
+                wait::waitid(Id::PIDFd(shell.pid_fd()), WaitPidFlag::WEXITED).unwrap();
+            }
+            unistd::close(shell.pid_fd()).unwrap();
+            unistd::close(shell.master_fd()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_render_background() {
+        let mut terminal = TestTerminal::new(4, 2);
+        assert_eq!(Some(FONT_COLOR), terminal.cell_color(0, 0));
+        assert_eq!(Some(BACKGROUND_COLOR), terminal.cell_color(1, 0));
+
+        terminal.push_bytes(b"\x1b[48;2;255;0;0m  \x1b[m");
+        assert_eq!(Some(RED), terminal.cell_color(0, 0));
+        assert_eq!(Some(RED), terminal.cell_color(1, 0));
+        assert_eq!(Some(FONT_COLOR), terminal.cell_color(2, 0));
+        assert_eq!(Some(BACKGROUND_COLOR), terminal.cell_color(0, 1));
+    }
+
+    #[test]
+    fn test_render_character() {
+        let mut terminal = TestTerminal::new(4, 2);
+        terminal.push_bytes(b"\x1b[2CA");
+        // Glyph is drawn over the background, so the cell is not filled with a single color.
+        assert_eq!(None, terminal.cell_color(2, 0));
+        assert_eq!(Some(BACKGROUND_COLOR), terminal.cell_color(1, 0));
+
+        // Glyph reaching above the cell is clipped to it.
+        terminal.push_bytes("\u{1D5}\r".as_bytes());
+        assert_eq!(None, terminal.cell_color(3, 0));
+
+        // Bold copy of a glyph filling the cell is clipped at the edge of the display.
+        terminal.push_bytes("\x1b[2;4H\x1b[1m\u{2588}\x1b[H".as_bytes());
+        // Block leaves the last column and line of the cell partly uncovered.
+        let origin = Point::<CellsUnit>::new(3, 1).to_pixels(terminal.cell_size);
+        let size = Dimensions::new(
+            terminal.cell_size.width() - 1,
+            terminal.cell_size.height() - 1,
+        );
+        let glyph = Rectangle::new(origin, size);
+        assert!(terminal.frame.is_filled(glyph, FONT_COLOR));
+    }
+
+    #[test]
+    fn test_render_scroll() {
+        let mut terminal = TestTerminal::new(4, 3);
+        terminal.push_bytes(b"\x1b[2;1H\x1b[48;2;255;0;0m \x1b[m\x1b[3;1H\n");
+        assert_eq!(Some(RED), terminal.cell_color(0, 0));
+        assert_eq!(Some(BACKGROUND_COLOR), terminal.cell_color(0, 1));
+        assert_eq!(Some(FONT_COLOR), terminal.cell_color(0, 2));
+
+        terminal.push_bytes(b"\x1b[H\x1b[L");
+        assert_eq!(Some(FONT_COLOR), terminal.cell_color(0, 0));
+        assert_eq!(Some(RED), terminal.cell_color(0, 1));
+        assert_eq!(Some(BACKGROUND_COLOR), terminal.cell_color(0, 2));
+    }
+
+    proptest! {
+        // Every case spawns a shell, so there are fewer of them than by default.
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn test_arbitrary_output(chunks in prop::collection::vec(output_chunk(), 0..32)) {
+            let mut terminal = TestTerminal::new(8, 4);
+            for chunk in chunks {
+                prop_assert!(terminal.terminal.handle_bytes(&chunk).is_ok());
+            }
+        }
+    }
+
+    /// Returns output of the shell, which is either arbitrary or a control sequence with extreme
+    /// parameters.
+    fn output_chunk() -> impl Strategy<Value = Vec<u8>> {
+        let parameter = prop_oneof![Just(0u64), 1..10u64, Just(65535), Just(u64::MAX)];
+        let sequence = (
+            prop::collection::vec(parameter, 0..4),
+            prop::sample::select(b"?>".to_vec()),
+            any::<bool>(),
+            0x40..=0x7Eu8,
+        )
+            .prop_map(|(parameters, marker, private, final_byte)| {
+                let parameters: Vec<_> = parameters.iter().map(u64::to_string).collect();
+                let mut bytes = b"\x1b[".to_vec();
+                if private {
+                    bytes.push(marker);
+                }
+                bytes.extend(parameters.join(";").bytes());
+                bytes.push(final_byte);
+                bytes
+            });
+        prop_oneof![prop::collection::vec(any::<u8>(), 0..64), sequence]
+    }
+
+    #[test]
+    fn test_save_screenshot() {
+        let terminal = TestTerminal::new(2, 1);
+        let settings = ScreenshotSettings {
+            directory: env::temp_dir(),
+            format: ScreenshotFormat::Ppm,
+            on_exit: false,
+        };
+        let path = terminal
+            .terminal
+            .renderer
+            .save_screenshot(&settings)
+            .unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let size = terminal.frame.size();
+        let header = format!("P6\n{} {}\n255\n", size.width(), size.height());
+        assert!(bytes.starts_with(header.as_bytes()));
+        // Cursor is drawn in the top left corner.
+        let first_pixel = &bytes[header.len()..header.len() + 3];
+        assert_eq!(
+            [FONT_COLOR.red(), FONT_COLOR.green(), FONT_COLOR.blue()].as_slice(),
+            first_pixel
+        );
+    }
+
+    #[test]
+    fn test_move_cursor_far() {
+        let mut terminal = TestTerminal::new(4, 3);
+        terminal.push_bytes(b"\x1b[3;3H\x1b[65535A");
+        assert_eq!(Point::new(2, 0), terminal.terminal.cells.current_cell());
+        terminal.push_bytes(b"\x1b[3;3H\x1b[65535D");
+        assert_eq!(Point::new(0, 0), terminal.terminal.cells.current_cell());
+    }
+
+    #[test]
+    fn test_move_cursor_in_scroll_region() {
+        let mut terminal = TestTerminal::new(4, 4);
+        terminal.push_bytes(b"\x1b[2;3r\x1b[3;2H\x1b[9A");
+        assert_eq!(Point::new(1, 1), terminal.terminal.cells.current_cell());
+        terminal.push_bytes(b"\x1b[9B");
+        assert_eq!(Point::new(1, 2), terminal.terminal.cells.current_cell());
+    }
+
+    #[test]
+    fn test_save_dump() {
+        let mut terminal = TestTerminal::new(4, 2);
+        terminal.push_bytes(b"ab \r\n\x1b[31mc\x1b[m");
+        let terminal = &terminal.terminal;
+        let path = terminal
+            .dump_settings
+            .save(&terminal.cells, DumpFormat::Ansi, DUMP_PALETTE)
+            .unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            Some("ans"),
+            path.extension().and_then(|extension| extension.to_str())
+        );
+        assert_eq!("ab\n\x1b[0;31mc\x1b[0m\n", text);
+    }
+
+    #[test]
+    fn test_dump_requests() {
+        let mut terminal = TestTerminal::new(4, 2);
+        let directory = env::temp_dir().join(format!("ft-test-dump-requests-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        terminal.terminal.dump_settings.directory = directory.clone();
+        let count_dumps = || fs::read_dir(&directory).unwrap().count();
+
+        terminal.push_bytes(b"\x1b[i");
+        assert_eq!(0, count_dumps());
+        terminal.terminal.dump_settings.requests = true;
+        terminal.push_bytes(b"\x1b[i\x1b[i\x1b[10i");
+        assert_eq!(1, count_dumps());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
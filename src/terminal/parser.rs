@@ -1,8 +1,30 @@
-use anyhow::{anyhow, Result};
+use std::mem;
+use std::slice::Iter;
 
-const BELL: u8 = 7;
-const BACKSPACE: u8 = 8;
-const ESCAPE: u8 = 27;
+use crate::color::Rgb;
+use crate::spatial::point::Point;
+use crate::spatial::CellsUnit;
+use crate::terminal::cells::charset::{Charset, CharsetSlot};
+use crate::terminal::cells::dump::DumpFormat;
+use crate::terminal::cells::style::{Attributes, Color};
+
+const BELL: u8 = 0x07;
+const BACKSPACE: u8 = 0x08;
+const VERTICAL_TABULATION: u8 = 0x0B;
+const FORM_FEED: u8 = 0x0C;
+const SHIFT_OUT: u8 = 0x0E;
+const SHIFT_IN: u8 = 0x0F;
+const CANCEL: u8 = 0x18;
+const SUBSTITUTE: u8 = 0x1A;
+const ESCAPE: u8 = 0x1B;
+const REPLACEMENT_CHARACTER: char = '\u{FFFD}';
+/// Limits of a control sequence, above which further parameters, sub-parameters and intermediate
+/// bytes are dropped. Sub-parameters have to fit `38:2:colorspace:red:green:blue`.
+const MAX_PARAMETERS: usize = 32;
+const MAX_SUB_PARAMETERS: usize = 8;
+const MAX_INTERMEDIATES: usize = 2;
+/// Limit of parameter values, to which larger ones saturate, like in xterm.
+const MAX_PARAMETER_VALUE: u32 = u16::MAX as u32;
 
 #[derive(Debug)]
 pub enum ParserAction {
@@ -14,178 +36,1043 @@ pub enum ParserAction {
     MoveCursorForward(u32),
     MoveCursorBack(u32),
     MoveCursorToNextMultipleOf(u32),
+    MoveCursorTo(Point<CellsUnit>),
+    MoveCursorToColumn(u32),
+    MoveCursorToLine(u32),
+    MoveCursorToNextLine(u32),
+    MoveCursorToPreviousLine(u32),
     EnableBracketedPasteMode,
     DisableBracketedPasteMode,
-    Clear,
+    EnableAlternateScreen(AlternateScreenMode),
+    DisableAlternateScreen(AlternateScreenMode),
+    SelectGraphicRendition(Vec<GraphicRendition>),
+    EraseInDisplay(DisplayErasure),
+    EraseInLine(LineErasure),
+    EraseCharacters(u32),
+    InsertCharacters(u32),
+    DeleteCharacters(u32),
+    InsertLines(u32),
+    DeleteLines(u32),
+    EnableInsertMode,
+    DisableInsertMode,
+    SetScrollRegion {
+        top: u32,
+        bottom: Option<u32>,
+    },
+    ScrollUp(u32),
+    ScrollDown(u32),
+    ReverseIndex,
+    NextLine,
+    SaveCursor,
+    RestoreCursor,
+    DesignateCharset(CharsetSlot, Charset),
+    ShiftCharset(CharsetSlot),
+    EnableOriginMode,
+    DisableOriginMode,
+    ReportDeviceStatus,
+    ReportCursorPosition,
+    ReportExtendedCursorPosition,
+    ReportPrimaryDeviceAttributes,
+    ReportSecondaryDeviceAttributes,
+    ReportKeyboardFlags,
+    /// Saves text of the screen in the given format, or in the configured one if there is none.
+    DumpScreen(Option<DumpFormat>),
     MoreBytes,
     Ignore,
     UnsupportedSequence,
 }
 
+/// Variants of the alternate screen private modes, which differ in handling of screen content and
+/// cursor when switching between screens.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AlternateScreenMode {
+    /// Mode 47, which only switches screens.
+    Plain,
+    /// Mode 1047, which clears the alternate screen when leaving it.
+    ClearOnExit,
+    /// Mode 1049, which saves cursor and clears the alternate screen when entering it and restores
+    /// cursor when leaving it.
+    SaveCursor,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DisplayErasure {
+    Below,
+    Above,
+    All,
+    Scrollback,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LineErasure {
+    Right,
+    Left,
+    All,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GraphicRendition {
+    Reset,
+    Foreground(Color),
+    Background(Color),
+    SetAttributes(Attributes),
+    ResetAttributes(Attributes),
+}
+
+/// States of the parser, following the state machine of DEC terminals described by Paul Williams
+/// at <https://vt100.net/emu/dec_ansi_parser>. Every sequence is consumed up to its final byte, even
+/// if it is not supported, so its bytes never end up on the screen.
 #[derive(Debug)]
 pub enum ParserState {
-    Empty,
+    Ground,
     Escape,
-    Csi,
-    CsiPrivate,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParameter,
+    CsiIntermediate,
+    /// Malformed control sequence, which is consumed up to its final byte without any effect.
+    CsiIgnore,
+    /// Parameters and intermediates of a device control string, up to its final byte.
+    DcsEntry,
+    /// Data of a device control string. None is supported, so the data is ignored up to the string
+    /// terminator.
+    DcsPassthrough,
+    OscString,
+    /// Start of string, privacy message or application program command, which are all ignored.
+    SosPmApcString,
 }
 
 #[derive(Debug)]
 pub struct Parser {
     state: ParserState,
-    buffer: Vec<u8>,
-    numbers: Vec<u32>,
+    /// Value of the parameter or sub-parameter being parsed, if any of its digits came already.
+    value: Option<u32>,
+    parameters: Vec<Vec<u32>>,
+    sub_parameters: Vec<u32>,
+    /// Byte between 0x3C and 0x3F opening a control sequence, like `?` of private modes.
+    private_marker: Option<u8>,
+    intermediates: Vec<u8>,
+    decoder: Utf8Decoder,
 }
 
 impl Parser {
     pub fn new() -> Self {
         Self {
-            state: ParserState::Empty,
-            buffer: Vec::with_capacity(16),
-            numbers: Vec::with_capacity(4),
+            state: ParserState::Ground,
+            value: None,
+            parameters: Vec::with_capacity(4),
+            sub_parameters: Vec::with_capacity(4),
+            private_marker: None,
+            intermediates: Vec::with_capacity(MAX_INTERMEDIATES),
+            decoder: Utf8Decoder::new(),
+        }
+    }
+
+    /// Parses the bytes, continuing any sequence left incomplete by the previous call. Parsing never
+    /// fails, as malformed sequences are either ignored or shown as replacement characters.
+    /// Actions are appended to the given vector, so the caller can reuse it between calls.
+    pub fn push_bytes(&mut self, bytes: &[u8], actions: &mut Vec<ParserAction>) {
+        for byte in bytes {
+            self.push_byte(*byte, actions);
         }
     }
 
-    pub fn push_byte(&mut self, byte: u8) -> Result<ParserAction> {
+    fn push_byte(&mut self, byte: u8, actions: &mut Vec<ParserAction>) {
+        // Outside of the ground state these bytes interrupt any sequence. In the ground state they
+        // are handled after decoding, as they may interrupt a partial UTF-8 sequence too.
+        if !matches!(self.state, ParserState::Ground) {
+            match byte {
+                CANCEL | SUBSTITUTE => {
+                    self.state = ParserState::Ground;
+                    actions.push(ParserAction::Ignore);
+                    return;
+                }
+                ESCAPE => {
+                    self.enter_escape();
+                    return;
+                }
+                _ => {}
+            }
+        }
+
         let action = match self.state {
-            ParserState::Empty => self.parse_empty(byte),
+            ParserState::Ground => match self.decoder.push_byte(byte) {
+                Utf8Result::Character(character) => self.parse_ground(character),
+                Utf8Result::Incomplete => ParserAction::MoreBytes,
+                Utf8Result::Invalid => ParserAction::InsertCharacter(REPLACEMENT_CHARACTER),
+                Utf8Result::Interrupted => {
+                    // The byte which interrupted the sequence may start a new one, so it has to be
+                    // parsed again after reporting the invalid sequence.
+                    actions.push(ParserAction::InsertCharacter(REPLACEMENT_CHARACTER));
+                    return self.push_byte(byte, actions);
+                }
+            },
             ParserState::Escape => self.parse_escape(byte),
-            ParserState::Csi => self.parse_csi(byte),
-            ParserState::CsiPrivate => self.parse_csi_private(byte),
-        }?;
+            ParserState::EscapeIntermediate => self.parse_escape_intermediate(byte),
+            ParserState::CsiEntry => self.parse_csi_entry(byte, actions),
+            ParserState::CsiParameter => self.parse_csi_parameter(byte, actions),
+            ParserState::CsiIntermediate => self.parse_csi_intermediate(byte, actions),
+            ParserState::CsiIgnore => self.parse_csi_ignore(byte),
+            ParserState::DcsEntry => self.parse_dcs_entry(byte),
+            ParserState::DcsPassthrough => ParserAction::MoreBytes,
+            ParserState::OscString => self.parse_osc_string(byte),
+            ParserState::SosPmApcString => ParserAction::MoreBytes,
+        };
         if !matches!(action, ParserAction::MoreBytes) {
-            self.state = ParserState::Empty;
+            actions.push(action);
+        }
+    }
+
+    fn parse_ground(&mut self, character: char) -> ParserAction {
+        const ESCAPE_CHARACTER: char = ESCAPE as char;
+
+        match character {
+            ESCAPE_CHARACTER => {
+                self.enter_escape();
+                ParserAction::MoreBytes
+            }
+            '\0'..='\x1F' => Self::execute(character as u8),
+            // Delete and C1 controls, which are not used in UTF-8 mode, have nothing to show.
+            '\x7F'..='\u{9F}' => ParserAction::Ignore,
+            _ => ParserAction::InsertCharacter(character),
         }
-        Ok(action)
     }
 
-    fn parse_empty(&mut self, byte: u8) -> Result<ParserAction> {
-        Ok(match byte {
-            BELL => ParserAction::Ignore,
+    /// Returns action of the C0 control, which is executed in the middle of sequences as well.
+    fn execute(byte: u8) -> ParserAction {
+        match byte {
             BACKSPACE => ParserAction::MoveCursorBack(1),
             b'\r' => ParserAction::CarriageReturn,
-            b'\n' => ParserAction::NewLine,
+            // Vertical tabulation and form feed are treated as line feed, like in DEC terminals.
+            b'\n' | VERTICAL_TABULATION | FORM_FEED => ParserAction::NewLine,
             b'\t' => ParserAction::MoveCursorToNextMultipleOf(8),
-            ESCAPE => {
-                self.state = ParserState::Escape;
+            SHIFT_OUT => ParserAction::ShiftCharset(CharsetSlot::G1),
+            SHIFT_IN => ParserAction::ShiftCharset(CharsetSlot::G0),
+            _ => ParserAction::Ignore,
+        }
+    }
+
+    fn enter_escape(&mut self) {
+        self.value = None;
+        self.parameters.clear();
+        self.sub_parameters.clear();
+        self.private_marker = None;
+        self.intermediates.clear();
+        self.state = ParserState::Escape;
+    }
+
+    /// Collects the intermediate byte. Bytes over the limit are dropped, so a long sequence cannot
+    /// grow the parser without bound. Such sequence matches no supported one anyway.
+    fn collect(&mut self, byte: u8) {
+        if self.intermediates.len() < MAX_INTERMEDIATES {
+            self.intermediates.push(byte);
+        }
+    }
+
+    fn parse_escape(&mut self, byte: u8) -> ParserAction {
+        match byte {
+            0x00..=0x1F => Self::execute(byte),
+            0x20..=0x2F => {
+                self.collect(byte);
+                self.state = ParserState::EscapeIntermediate;
                 ParserAction::MoreBytes
             }
-            _ => ParserAction::InsertCharacter(byte as char),
-        })
+            b'[' => {
+                self.state = ParserState::CsiEntry;
+                ParserAction::MoreBytes
+            }
+            b']' => {
+                self.state = ParserState::OscString;
+                ParserAction::MoreBytes
+            }
+            b'P' => {
+                self.state = ParserState::DcsEntry;
+                ParserAction::MoreBytes
+            }
+            b'X' | b'^' | b'_' => {
+                self.state = ParserState::SosPmApcString;
+                ParserAction::MoreBytes
+            }
+            0x30..=0x7E => {
+                self.state = ParserState::Ground;
+                self.dispatch_escape(byte)
+            }
+            _ => ParserAction::MoreBytes,
+        }
     }
 
-    fn parse_escape(&mut self, byte: u8) -> Result<ParserAction> {
-        Ok(match byte {
-            b'[' => {
-                self.state = ParserState::Csi;
+    fn parse_escape_intermediate(&mut self, byte: u8) -> ParserAction {
+        match byte {
+            0x00..=0x1F => Self::execute(byte),
+            0x20..=0x2F => {
+                self.collect(byte);
                 ParserAction::MoreBytes
             }
+            0x30..=0x7E => {
+                self.state = ParserState::Ground;
+                self.dispatch_escape(byte)
+            }
+            _ => ParserAction::MoreBytes,
+        }
+    }
+
+    fn dispatch_escape(&self, byte: u8) -> ParserAction {
+        match (self.intermediates.as_slice(), byte) {
+            ([], b'7') => ParserAction::SaveCursor,
+            ([], b'8') => ParserAction::RestoreCursor,
+            ([], b'D') => ParserAction::NewLine,
+            ([], b'E') => ParserAction::NextLine,
+            ([], b'M') => ParserAction::ReverseIndex,
+            // String terminator, which ends an ignored string.
+            ([], b'\\') => ParserAction::Ignore,
+            ([b'('], _) => Self::designate_charset(CharsetSlot::G0, byte),
+            ([b')'], _) => Self::designate_charset(CharsetSlot::G1, byte),
+            _ => ParserAction::UnsupportedSequence,
+        }
+    }
+
+    fn designate_charset(slot: CharsetSlot, byte: u8) -> ParserAction {
+        match byte {
+            b'0' => ParserAction::DesignateCharset(slot, Charset::DecSpecialGraphics),
+            b'B' => ParserAction::DesignateCharset(slot, Charset::Ascii),
             _ => ParserAction::UnsupportedSequence,
-        })
+        }
     }
 
-    fn parse_csi(&mut self, byte: u8) -> Result<ParserAction> {
-        Ok(match byte {
-            b'?' => {
-                self.state = ParserState::CsiPrivate;
+    fn parse_csi_entry(&mut self, byte: u8, actions: &mut Vec<ParserAction>) -> ParserAction {
+        match byte {
+            0x00..=0x1F => Self::execute(byte),
+            0x3C..=0x3F => {
+                self.private_marker = Some(byte);
+                self.state = ParserState::CsiParameter;
                 ParserAction::MoreBytes
             }
+            _ => {
+                self.state = ParserState::CsiParameter;
+                self.parse_csi_parameter(byte, actions)
+            }
+        }
+    }
+
+    fn parse_csi_parameter(&mut self, byte: u8, actions: &mut Vec<ParserAction>) -> ParserAction {
+        match byte {
+            0x00..=0x1F => Self::execute(byte),
             b'0'..=b'9' => {
-                self.buffer.push(byte);
+                self.push_digit(byte);
+                ParserAction::MoreBytes
+            }
+            b':' => {
+                self.push_sub_parameter();
                 ParserAction::MoreBytes
             }
             b';' => {
-                self.push_number()?;
+                self.push_parameter();
                 ParserAction::MoreBytes
             }
-            _ => {
-                self.push_number()?;
-                match byte {
-                    b'A' => {
-                        if let Some(number) = self.numbers.last().copied() {
-                            self.numbers.clear();
-                            ParserAction::MoveCursorUp(number)
-                        } else {
-                            ParserAction::MoveCursorUp(1)
-                        }
-                    }
-                    b'B' => {
-                        if let Some(number) = self.numbers.last().copied() {
-                            self.numbers.clear();
-                            ParserAction::MoveCursorDown(number)
-                        } else {
-                            ParserAction::MoveCursorDown(1)
-                        }
-                    }
-                    b'C' => {
-                        if let Some(number) = self.numbers.last().copied() {
-                            self.numbers.clear();
-                            ParserAction::MoveCursorForward(number)
-                        } else {
-                            ParserAction::MoveCursorForward(1)
-                        }
-                    }
-                    b'D' => {
-                        if let Some(number) = self.numbers.last().copied() {
-                            self.numbers.clear();
-                            ParserAction::MoveCursorBack(number)
-                        } else {
-                            ParserAction::MoveCursorBack(1)
-                        }
-                    }
-                    b'J' => {
-                        if let Some(3) = self.numbers.last().copied() {
-                            self.numbers.clear();
-                            ParserAction::Clear
-                        } else {
-                            ParserAction::UnsupportedSequence
-                        }
-                    }
-                    _ => ParserAction::UnsupportedSequence,
-                }
+            0x3C..=0x3F => {
+                self.state = ParserState::CsiIgnore;
+                ParserAction::MoreBytes
             }
-        })
+            0x20..=0x2F => {
+                self.push_parameter();
+                self.collect(byte);
+                self.state = ParserState::CsiIntermediate;
+                ParserAction::MoreBytes
+            }
+            0x40..=0x7E => {
+                self.push_parameter();
+                self.state = ParserState::Ground;
+                self.dispatch_csi(byte, actions);
+                ParserAction::MoreBytes
+            }
+            _ => ParserAction::MoreBytes,
+        }
     }
 
-    fn parse_csi_private(&mut self, byte: u8) -> Result<ParserAction> {
-        Ok(match byte {
-            b'0'..=b'9' => {
-                self.buffer.push(byte);
+    fn parse_csi_intermediate(
+        &mut self,
+        byte: u8,
+        actions: &mut Vec<ParserAction>,
+    ) -> ParserAction {
+        match byte {
+            0x00..=0x1F => Self::execute(byte),
+            0x20..=0x2F => {
+                self.collect(byte);
                 ParserAction::MoreBytes
             }
-            b'h' => {
-                let number = self.parse_number()?;
-                match number {
-                    Some(2004) => ParserAction::EnableBracketedPasteMode,
-                    _ => ParserAction::UnsupportedSequence,
+            0x30..=0x3F => {
+                self.state = ParserState::CsiIgnore;
+                ParserAction::MoreBytes
+            }
+            0x40..=0x7E => {
+                self.state = ParserState::Ground;
+                self.dispatch_csi(byte, actions);
+                ParserAction::MoreBytes
+            }
+            _ => ParserAction::MoreBytes,
+        }
+    }
+
+    fn parse_csi_ignore(&mut self, byte: u8) -> ParserAction {
+        match byte {
+            0x00..=0x1F => Self::execute(byte),
+            0x40..=0x7E => {
+                self.state = ParserState::Ground;
+                ParserAction::UnsupportedSequence
+            }
+            _ => ParserAction::MoreBytes,
+        }
+    }
+
+    /// Pushes actions of the sequence ended by the byte. Most sequences result in a single action,
+    /// but modes are set or reset for each of the parameters.
+    fn dispatch_csi(&self, byte: u8, actions: &mut Vec<ParserAction>) {
+        if !self.intermediates.is_empty() {
+            actions.push(ParserAction::UnsupportedSequence);
+            return;
+        }
+        match (self.private_marker, byte) {
+            (None | Some(b'?'), b'h' | b'l') => {
+                let enable = byte == b'h';
+                let private = self.private_marker.is_some();
+                for parameter in &self.parameters {
+                    let mode = parameter[0];
+                    actions.push(if private {
+                        Self::dispatch_private_mode(mode, enable)
+                    } else {
+                        Self::dispatch_mode(mode, enable)
+                    });
                 }
             }
-            b'l' => {
-                let number = self.parse_number()?;
-                match number {
-                    Some(2004) => ParserAction::DisableBracketedPasteMode,
-                    _ => ParserAction::UnsupportedSequence,
+            (None, _) => actions.push(self.dispatch_csi_plain(byte)),
+            (Some(b'?'), _) => actions.push(self.dispatch_csi_private(byte)),
+            (Some(b'>'), _) => actions.push(self.dispatch_csi_secondary(byte)),
+            (Some(_), _) => actions.push(ParserAction::UnsupportedSequence),
+        }
+    }
+
+    fn dispatch_csi_plain(&self, byte: u8) -> ParserAction {
+        match byte {
+            b'A' => ParserAction::MoveCursorUp(self.parameter_or(0, 1)),
+            b'B' => ParserAction::MoveCursorDown(self.parameter_or(0, 1)),
+            b'C' => ParserAction::MoveCursorForward(self.parameter_or(0, 1)),
+            b'D' => ParserAction::MoveCursorBack(self.parameter_or(0, 1)),
+            b'E' => ParserAction::MoveCursorToNextLine(self.parameter_or(0, 1)),
+            b'F' => ParserAction::MoveCursorToPreviousLine(self.parameter_or(0, 1)),
+            // Parameters are 1-based, while cells are addressed from 0.
+            b'G' | b'`' => ParserAction::MoveCursorToColumn(self.parameter_or(0, 1) - 1),
+            b'd' => ParserAction::MoveCursorToLine(self.parameter_or(0, 1) - 1),
+            b'H' | b'f' => {
+                let line = self.parameter_or(0, 1) - 1;
+                let column = self.parameter_or(1, 1) - 1;
+                ParserAction::MoveCursorTo(Point::new(column, line))
+            }
+            b'J' => match self.parameter_or(0, 0) {
+                0 => ParserAction::EraseInDisplay(DisplayErasure::Below),
+                1 => ParserAction::EraseInDisplay(DisplayErasure::Above),
+                2 => ParserAction::EraseInDisplay(DisplayErasure::All),
+                3 => ParserAction::EraseInDisplay(DisplayErasure::Scrollback),
+                _ => ParserAction::UnsupportedSequence,
+            },
+            b'K' => match self.parameter_or(0, 0) {
+                0 => ParserAction::EraseInLine(LineErasure::Right),
+                1 => ParserAction::EraseInLine(LineErasure::Left),
+                2 => ParserAction::EraseInLine(LineErasure::All),
+                _ => ParserAction::UnsupportedSequence,
+            },
+            b'X' => ParserAction::EraseCharacters(self.parameter_or(0, 1)),
+            b'@' => ParserAction::InsertCharacters(self.parameter_or(0, 1)),
+            b'P' => ParserAction::DeleteCharacters(self.parameter_or(0, 1)),
+            b'L' => ParserAction::InsertLines(self.parameter_or(0, 1)),
+            b'M' => ParserAction::DeleteLines(self.parameter_or(0, 1)),
+            b'c' => match self.parameter_or(0, 0) {
+                0 => ParserAction::ReportPrimaryDeviceAttributes,
+                _ => ParserAction::UnsupportedSequence,
+            },
+            b'n' => match self.parameter_or(0, 0) {
+                5 => ParserAction::ReportDeviceStatus,
+                6 => ParserAction::ReportCursorPosition,
+                _ => ParserAction::UnsupportedSequence,
+            },
+            // Media copy, which prints the screen on real terminals. Like in xterm, 10 asks for HTML.
+            b'i' => match self.parameter_or(0, 0) {
+                0 => ParserAction::DumpScreen(None),
+                10 => ParserAction::DumpScreen(Some(DumpFormat::Html)),
+                _ => ParserAction::UnsupportedSequence,
+            },
+            b's' => ParserAction::SaveCursor,
+            b'u' => ParserAction::RestoreCursor,
+            b'S' => ParserAction::ScrollUp(self.parameter_or(0, 1)),
+            b'T' => ParserAction::ScrollDown(self.parameter_or(0, 1)),
+            b'r' => {
+                let top = self.parameter_or(0, 1) - 1;
+                let bottom = Some(self.parameter_or(1, 0)).filter(|bottom| *bottom != 0);
+                ParserAction::SetScrollRegion { top, bottom }
+            }
+            b'm' => {
+                let renditions = self.parse_graphic_renditions();
+                ParserAction::SelectGraphicRendition(renditions)
+            }
+            _ => ParserAction::UnsupportedSequence,
+        }
+    }
+
+    fn parse_graphic_renditions(&self) -> Vec<GraphicRendition> {
+        let mut renditions = Vec::with_capacity(self.parameters.len());
+        let mut parameters = self.parameters.iter();
+        while let Some(parameter) = parameters.next() {
+            let set = |attributes| Some(GraphicRendition::SetAttributes(attributes));
+            let reset = |attributes| Some(GraphicRendition::ResetAttributes(attributes));
+            let rendition = match parameter[0] {
+                0 => Some(GraphicRendition::Reset),
+                1 => set(Attributes::BOLD),
+                2 => set(Attributes::FAINT),
+                3 => set(Attributes::ITALIC),
+                4 => match parameter.get(1) {
+                    None | Some(1) => set(Attributes::UNDERLINE),
+                    Some(0) => reset(Attributes::ALL_UNDERLINES),
+                    Some(2) => set(Attributes::DOUBLE_UNDERLINE),
+                    // Dotted and dashed underlines are drawn as curly ones.
+                    Some(3..=5) => set(Attributes::CURLY_UNDERLINE),
+                    Some(_) => None,
+                },
+                5 | 6 => set(Attributes::BLINK),
+                7 => set(Attributes::INVERSE),
+                8 => set(Attributes::INVISIBLE),
+                9 => set(Attributes::STRIKETHROUGH),
+                21 => set(Attributes::DOUBLE_UNDERLINE),
+                22 => reset(Attributes::BOLD.union(Attributes::FAINT)),
+                23 => reset(Attributes::ITALIC),
+                24 => reset(Attributes::ALL_UNDERLINES),
+                25 => reset(Attributes::BLINK),
+                27 => reset(Attributes::INVERSE),
+                28 => reset(Attributes::INVISIBLE),
+                29 => reset(Attributes::STRIKETHROUGH),
+                code @ 30..=37 => Some(GraphicRendition::Foreground(Color::Indexed(
+                    code as u8 - 30,
+                ))),
+                38 => Self::parse_extended_color(parameter, &mut parameters)
+                    .map(GraphicRendition::Foreground),
+                39 => Some(GraphicRendition::Foreground(Color::Default)),
+                code @ 40..=47 => Some(GraphicRendition::Background(Color::Indexed(
+                    code as u8 - 40,
+                ))),
+                48 => Self::parse_extended_color(parameter, &mut parameters)
+                    .map(GraphicRendition::Background),
+                49 => Some(GraphicRendition::Background(Color::Default)),
+                code @ 90..=97 => Some(GraphicRendition::Foreground(Color::Indexed(
+                    code as u8 - 90 + 8,
+                ))),
+                code @ 100..=107 => Some(GraphicRendition::Background(Color::Indexed(
+                    code as u8 - 100 + 8,
+                ))),
+                code => {
+                    log::debug!("Unsupported graphic rendition: {}", code);
+                    None
                 }
+            };
+            if let Some(rendition) = rendition {
+                renditions.push(rendition);
             }
+        }
+        renditions
+    }
+
+    fn parse_extended_color(parameter: &[u32], parameters: &mut Iter<Vec<u32>>) -> Option<Color> {
+        if parameter.len() > 1 {
+            // Sub-parameters separated by colons, i.e. `38:5:index` or `38:2:[space]:r:g:b`.
+            return match parameter[1..] {
+                [5, index] => Self::parse_indexed_color(index),
+                [2, red, green, blue] | [2, _, red, green, blue] => {
+                    Self::parse_rgb_color(red, green, blue)
+                }
+                _ => None,
+            };
+        }
+
+        // Parameters separated by semicolons, i.e. `38;5;index` or `38;2;r;g;b`.
+        let mut next_value = || parameters.next().map(|parameter| parameter[0]);
+        match next_value()? {
+            5 => Self::parse_indexed_color(next_value()?),
+            2 => Self::parse_rgb_color(next_value()?, next_value()?, next_value()?),
+            _ => None,
+        }
+    }
+
+    fn parse_indexed_color(index: u32) -> Option<Color> {
+        let index = u8::try_from(index).ok()?;
+        Some(Color::Indexed(index))
+    }
+
+    fn parse_rgb_color(red: u32, green: u32, blue: u32) -> Option<Color> {
+        let red = u8::try_from(red).ok()?;
+        let green = u8::try_from(green).ok()?;
+        let blue = u8::try_from(blue).ok()?;
+        Some(Color::Rgb(Rgb::new(red, green, blue)))
+    }
+
+    fn dispatch_mode(mode: u32, enable: bool) -> ParserAction {
+        match (mode, enable) {
+            (4, true) => ParserAction::EnableInsertMode,
+            (4, false) => ParserAction::DisableInsertMode,
             _ => ParserAction::UnsupportedSequence,
-        })
+        }
+    }
+
+    fn dispatch_private_mode(mode: u32, enable: bool) -> ParserAction {
+        match (mode, enable) {
+            (6, true) => ParserAction::EnableOriginMode,
+            (6, false) => ParserAction::DisableOriginMode,
+            (47, true) => ParserAction::EnableAlternateScreen(AlternateScreenMode::Plain),
+            (47, false) => ParserAction::DisableAlternateScreen(AlternateScreenMode::Plain),
+            (1047, true) => ParserAction::EnableAlternateScreen(AlternateScreenMode::ClearOnExit),
+            (1047, false) => ParserAction::DisableAlternateScreen(AlternateScreenMode::ClearOnExit),
+            (1049, true) => ParserAction::EnableAlternateScreen(AlternateScreenMode::SaveCursor),
+            (1049, false) => ParserAction::DisableAlternateScreen(AlternateScreenMode::SaveCursor),
+            (2004, true) => ParserAction::EnableBracketedPasteMode,
+            (2004, false) => ParserAction::DisableBracketedPasteMode,
+            _ => ParserAction::UnsupportedSequence,
+        }
+    }
+
+    fn dispatch_csi_private(&self, byte: u8) -> ParserAction {
+        match byte {
+            b'n' => match self.parameter_or(0, 0) {
+                6 => ParserAction::ReportExtendedCursorPosition,
+                _ => ParserAction::UnsupportedSequence,
+            },
+            b'u' => ParserAction::ReportKeyboardFlags,
+            _ => ParserAction::UnsupportedSequence,
+        }
+    }
+
+    fn dispatch_csi_secondary(&self, byte: u8) -> ParserAction {
+        match byte {
+            b'c' => match self.parameter_or(0, 0) {
+                0 => ParserAction::ReportSecondaryDeviceAttributes,
+                _ => ParserAction::UnsupportedSequence,
+            },
+            _ => ParserAction::UnsupportedSequence,
+        }
+    }
+
+    fn parse_dcs_entry(&mut self, byte: u8) -> ParserAction {
+        match byte {
+            0x40..=0x7E => {
+                self.state = ParserState::DcsPassthrough;
+                ParserAction::UnsupportedSequence
+            }
+            _ => ParserAction::MoreBytes,
+        }
+    }
+
+    fn parse_osc_string(&mut self, byte: u8) -> ParserAction {
+        match byte {
+            // Bell ends the string as well as the string terminator, like in xterm.
+            BELL => {
+                self.state = ParserState::Ground;
+                ParserAction::Ignore
+            }
+            _ => ParserAction::MoreBytes,
+        }
+    }
+
+    fn push_digit(&mut self, byte: u8) {
+        let digit = (byte - b'0') as u32;
+        let value = self
+            .value
+            .unwrap_or(0)
+            .saturating_mul(10)
+            .saturating_add(digit);
+        self.value = Some(value.min(MAX_PARAMETER_VALUE));
+    }
+
+    /// Ends the pending value as a sub-parameter. Values over the limit are dropped.
+    fn push_sub_parameter(&mut self) {
+        let value = self.value.take().unwrap_or(0);
+        if self.sub_parameters.len() < MAX_SUB_PARAMETERS {
+            self.sub_parameters.push(value);
+        }
+    }
+
+    /// Ends the pending parameter. Parameters over the limit are dropped, like in DEC terminals.
+    fn push_parameter(&mut self) {
+        self.push_sub_parameter();
+        let parameter = mem::take(&mut self.sub_parameters);
+        if self.parameters.len() < MAX_PARAMETERS {
+            self.parameters.push(parameter);
+        }
+    }
+
+    /// Returns first value of the parameter at given index or default if it is missing or zero.
+    fn parameter_or(&self, index: usize, default: u32) -> u32 {
+        match self.parameters.get(index) {
+            Some(parameter) if parameter[0] != 0 => parameter[0],
+            _ => default,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Utf8Result {
+    Character(char),
+    Incomplete,
+    /// Byte cannot start any sequence.
+    Invalid,
+    /// Byte is not a valid continuation of the pending sequence and has to be pushed again.
+    Interrupted,
+}
+
+/// Streaming UTF-8 decoder, which keeps partial sequences between pushed bytes.
+#[derive(Debug)]
+struct Utf8Decoder {
+    code_point: u32,
+    remaining: u8,
+    lower_boundary: u8,
+    upper_boundary: u8,
+}
+
+impl Utf8Decoder {
+    const CONTINUATION_LOWER_BOUNDARY: u8 = 0x80;
+    const CONTINUATION_UPPER_BOUNDARY: u8 = 0xBF;
+
+    fn new() -> Self {
+        Self {
+            code_point: 0,
+            remaining: 0,
+            lower_boundary: Self::CONTINUATION_LOWER_BOUNDARY,
+            upper_boundary: Self::CONTINUATION_UPPER_BOUNDARY,
+        }
+    }
+
+    fn push_byte(&mut self, byte: u8) -> Utf8Result {
+        if self.remaining == 0 {
+            return self.push_first_byte(byte);
+        }
+
+        if !(self.lower_boundary..=self.upper_boundary).contains(&byte) {
+            *self = Self::new();
+            return Utf8Result::Interrupted;
+        }
+
+        self.code_point = (self.code_point << 6) | (byte & 0x3F) as u32;
+        self.remaining -= 1;
+        self.lower_boundary = Self::CONTINUATION_LOWER_BOUNDARY;
+        self.upper_boundary = Self::CONTINUATION_UPPER_BOUNDARY;
+        if self.remaining > 0 {
+            return Utf8Result::Incomplete;
+        }
+
+        // Boundaries of the second byte exclude overlong encodings and surrogates, so the code
+        // point is always valid here.
+        let character = char::from_u32(self.code_point).unwrap_or(REPLACEMENT_CHARACTER);
+        self.code_point = 0;
+        Utf8Result::Character(character)
     }
 
-    fn push_number(&mut self) -> Result<()> {
-        let number = self.parse_number()?;
-        if let Some(number) = number {
-            self.numbers.push(number);
+    fn push_first_byte(&mut self, byte: u8) -> Utf8Result {
+        // Boundaries for the second byte are taken from the table 3-7 of the Unicode Standard.
+        let (remaining, mask, lower_boundary, upper_boundary) = match byte {
+            0x00..=0x7F => return Utf8Result::Character(byte as char),
+            0xC2..=0xDF => (1, 0x1F, 0x80, 0xBF),
+            0xE0 => (2, 0x0F, 0xA0, 0xBF),
+            0xE1..=0xEC | 0xEE..=0xEF => (2, 0x0F, 0x80, 0xBF),
+            0xED => (2, 0x0F, 0x80, 0x9F),
+            0xF0 => (3, 0x07, 0x90, 0xBF),
+            0xF1..=0xF3 => (3, 0x07, 0x80, 0xBF),
+            0xF4 => (3, 0x07, 0x80, 0x8F),
+            _ => return Utf8Result::Invalid,
+        };
+        self.code_point = (byte & mask) as u32;
+        self.remaining = remaining;
+        self.lower_boundary = lower_boundary;
+        self.upper_boundary = upper_boundary;
+        Utf8Result::Incomplete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn push_bytes(parser: &mut Parser, bytes: &[u8]) -> Vec<ParserAction> {
+        let mut actions = Vec::new();
+        parser.push_bytes(bytes, &mut actions);
+        actions
+    }
+
+    fn decode(bytes: &[u8]) -> String {
+        let mut decoder = Utf8Decoder::new();
+        let mut string = String::new();
+        for byte in bytes {
+            let mut result = decoder.push_byte(*byte);
+            if result == Utf8Result::Interrupted {
+                string.push(REPLACEMENT_CHARACTER);
+                result = decoder.push_byte(*byte);
+            }
+            match result {
+                Utf8Result::Character(character) => string.push(character),
+                Utf8Result::Invalid => string.push(REPLACEMENT_CHARACTER),
+                Utf8Result::Incomplete | Utf8Result::Interrupted => {}
+            }
+        }
+        string
+    }
+
+    #[test]
+    fn test_decode_valid() {
+        let string = "zażółć ─┼─ 日本 🦀";
+        assert_eq!(string, decode(string.as_bytes()));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!("\u{FFFD}a", decode(b"\xC3a"));
+        assert_eq!("\u{FFFD}\u{FFFD}", decode(b"\xC0\x80"));
+        assert_eq!("\u{FFFD}\u{FFFD}\u{FFFD}", decode(b"\xED\xA0\x80"));
+        assert_eq!("\u{FFFD}🦀", decode(b"\xF0\x9F\xA6\xF0\x9F\xA6\x80"));
+        assert_eq!("\u{FFFD}\u{1B}", decode(b"\xE2\x94\x1B"));
+    }
+
+    fn parse_graphic_renditions(bytes: &[u8]) -> Vec<GraphicRendition> {
+        let mut parser = Parser::new();
+        let mut actions = push_bytes(&mut parser, bytes);
+        match actions.pop() {
+            Some(ParserAction::SelectGraphicRendition(renditions)) if actions.is_empty() => {
+                renditions
+            }
+            action => panic!("Unexpected action: {:?}", action),
         }
-        Ok(())
     }
 
-    fn parse_number(&mut self) -> Result<Option<u32>> {
-        if self.buffer.is_empty() {
-            return Ok(None);
+    #[test]
+    fn test_parse_graphic_renditions() {
+        assert_eq!(
+            vec![
+                GraphicRendition::SetAttributes(Attributes::BOLD),
+                GraphicRendition::Foreground(Color::Indexed(1)),
+                GraphicRendition::Background(Color::Indexed(208)),
+                GraphicRendition::Foreground(Color::Indexed(12)),
+            ],
+            parse_graphic_renditions(b"\x1b[1;31;48;5;208;94m")
+        );
+        assert_eq!(
+            vec![
+                GraphicRendition::Foreground(Color::Rgb(Rgb::new(1, 2, 3))),
+                GraphicRendition::Background(Color::Rgb(Rgb::new(4, 5, 6))),
+                GraphicRendition::Background(Color::Default),
+                GraphicRendition::Reset,
+            ],
+            parse_graphic_renditions(b"\x1b[38:2::1:2:3;48;2;4;5;6;49;0m")
+        );
+        assert_eq!(
+            vec![GraphicRendition::Reset],
+            parse_graphic_renditions(b"\x1b[m")
+        );
+        assert_eq!(
+            vec![
+                GraphicRendition::SetAttributes(Attributes::CURLY_UNDERLINE),
+                GraphicRendition::ResetAttributes(Attributes::ALL_UNDERLINES),
+                GraphicRendition::ResetAttributes(Attributes::BOLD.union(Attributes::FAINT)),
+            ],
+            parse_graphic_renditions(b"\x1b[4:3;24;22m")
+        );
+    }
+
+    #[test]
+    fn test_parse_cursor_position() {
+        let mut parser = Parser::new();
+        let actions = push_bytes(&mut parser, b"\x1b[H\x1b[5;10H\x1b[;3f\x1b[7G\x1b[0d");
+        let cells = actions
+            .iter()
+            .map(|action| match action {
+                ParserAction::MoveCursorTo(cell) => {
+                    (cell.horizontal_distance(), cell.vertical_distance())
+                }
+                ParserAction::MoveCursorToColumn(column) => (*column, u32::MAX),
+                ParserAction::MoveCursorToLine(line) => (u32::MAX, *line),
+                action => panic!("Unexpected action: {:?}", action),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(0, 0), (9, 4), (2, 0), (6, u32::MAX), (u32::MAX, 0)],
+            cells
+        );
+    }
+
+    #[test]
+    fn test_parse_reports() {
+        let mut parser = Parser::new();
+        let actions = push_bytes(
+            &mut parser,
+            b"\x1b[5n\x1b[6n\x1b[?6n\x1b[c\x1b[>c\x1b[?u\x1b[i\x1b[10i",
+        );
+        assert!(matches!(
+            actions[..],
+            [
+                ParserAction::ReportDeviceStatus,
+                ParserAction::ReportCursorPosition,
+                ParserAction::ReportExtendedCursorPosition,
+                ParserAction::ReportPrimaryDeviceAttributes,
+                ParserAction::ReportSecondaryDeviceAttributes,
+                ParserAction::ReportKeyboardFlags,
+                ParserAction::DumpScreen(None),
+                ParserAction::DumpScreen(Some(DumpFormat::Html)),
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_parse_modes() {
+        let mut parser = Parser::new();
+        let actions = push_bytes(&mut parser, b"\x1b[20;4h\x1b[4;20l\x1b[h");
+        assert!(matches!(
+            actions[..],
+            [
+                ParserAction::UnsupportedSequence,
+                ParserAction::EnableInsertMode,
+                ParserAction::DisableInsertMode,
+                ParserAction::UnsupportedSequence,
+                ParserAction::UnsupportedSequence,
+            ]
+        ));
+
+        let actions = push_bytes(&mut parser, b"\x1b[?1049;2004h\x1b[?6;1049l");
+        assert!(matches!(
+            actions[..],
+            [
+                ParserAction::EnableAlternateScreen(AlternateScreenMode::SaveCursor),
+                ParserAction::EnableBracketedPasteMode,
+                ParserAction::DisableOriginMode,
+                ParserAction::DisableAlternateScreen(AlternateScreenMode::SaveCursor),
+            ]
+        ));
+    }
+
+    /// Returns characters inserted by the actions, checking that there are no other actions than
+    /// ignored sequences.
+    fn inserted_characters(actions: &[ParserAction]) -> String {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                ParserAction::InsertCharacter(character) => Some(*character),
+                ParserAction::Ignore | ParserAction::UnsupportedSequence => None,
+                action => panic!("Unexpected action: {:?}", action),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unsupported_sequences_consumed() {
+        let mut parser = Parser::new();
+        let actions = push_bytes(
+            &mut parser,
+            b"a\x1b[?25lb\x1b[>4;1mc\x1b[2 qd\x1b[1<2me\x1b]0;title\x07f\x1b]2;\xC3\xB3\x1b\\g\
+                \x1bP1$r0m\x1b\\h\x1b_command\x1b\\i\x1b#8j\x1b(Ak\x7f",
+        );
+        assert_eq!("abcdefghijk", inserted_characters(&actions));
+    }
+
+    #[test]
+    fn test_controls_inside_sequences() {
+        let mut parser = Parser::new();
+        let actions = push_bytes(&mut parser, b"\x1b[1\r5A\x1b(\n0");
+        assert!(matches!(
+            actions[..],
+            [
+                ParserAction::CarriageReturn,
+                ParserAction::MoveCursorUp(15),
+                ParserAction::NewLine,
+                ParserAction::DesignateCharset(CharsetSlot::G0, Charset::DecSpecialGraphics),
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_interrupted_sequences() {
+        let mut parser = Parser::new();
+        let actions = push_bytes(&mut parser, b"\x1b[12\x18a\x1b]0;title\x1ab\x1b[12\x1b[3A");
+        assert!(matches!(
+            actions[..],
+            [
+                ParserAction::Ignore,
+                ParserAction::InsertCharacter('a'),
+                ParserAction::Ignore,
+                ParserAction::InsertCharacter('b'),
+                ParserAction::MoveCursorUp(3),
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_parameter_limits() {
+        let bytes = format!("\x1b[{}1m", "1;".repeat(100));
+        let renditions = parse_graphic_renditions(bytes.as_bytes());
+        assert_eq!(MAX_PARAMETERS, renditions.len());
+
+        let renditions = parse_graphic_renditions(b"\x1b[38:2:0:1:2:3:4:5:6:7:8:9m");
+        assert!(renditions.is_empty());
+    }
+
+    #[test]
+    fn test_saturated_parameters() {
+        let mut parser = Parser::new();
+        let actions = push_bytes(&mut parser, b"\x1b[99999999999999999999A\x1b[65536;70000H");
+        assert!(matches!(
+            actions[..],
+            [
+                ParserAction::MoveCursorUp(MAX_PARAMETER_VALUE),
+                ParserAction::MoveCursorTo(_),
+            ]
+        ));
+        let ParserAction::MoveCursorTo(cell) = actions[1] else {
+            unreachable!();
+        };
+        assert_eq!(MAX_PARAMETER_VALUE - 1, cell.horizontal_distance());
+        assert_eq!(MAX_PARAMETER_VALUE - 1, cell.vertical_distance());
+    }
+
+    /// Returns bytes mixing arbitrary ones with those which start and continue sequences, as
+    /// random bytes alone rarely form any.
+    fn sequence_bytes() -> impl Strategy<Value = Vec<u8>> {
+        let byte = prop_oneof![
+            any::<u8>(),
+            Just(ESCAPE),
+            Just(b'['),
+            Just(b']'),
+            Just(b'P'),
+            Just(BELL),
+            b'0'..=b'9',
+            prop::sample::select(b";:?> $".to_vec()),
+            0x40..=0x7Eu8,
+        ];
+        prop::collection::vec(byte, 0..256)
+    }
+
+    proptest! {
+        #[test]
+        fn test_arbitrary_bytes_recover(bytes in sequence_bytes(), split in any::<prop::sample::Index>()) {
+            // Bytes are pushed in two parts, so sequences split between pushes are covered too.
+            let mut parser = Parser::new();
+            let split = split.index(bytes.len() + 1);
+            push_bytes(&mut parser, &bytes[..split]);
+            push_bytes(&mut parser, &bytes[split..]);
+
+            // Cancel ends whatever sequence was left incomplete.
+            let actions = push_bytes(&mut parser, b"\x18a");
+            prop_assert!(matches!(actions.last(), Some(ParserAction::InsertCharacter('a'))));
         }
-        let number = atoi::atoi(&self.buffer)
-            .ok_or_else(|| anyhow!("Cannot parse number in escape sequence"))?;
-        self.buffer.clear();
-        Ok(Some(number))
+    }
+
+    #[test]
+    fn test_sequence_split_between_pushes() {
+        let mut parser = Parser::new();
+        let bytes = "ó".as_bytes();
+        let actions = push_bytes(&mut parser, &bytes[..1]);
+        assert!(actions.is_empty());
+        let actions = push_bytes(&mut parser, &bytes[1..]);
+        assert!(matches!(actions[..], [ParserAction::InsertCharacter('ó')]));
     }
 }
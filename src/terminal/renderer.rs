@@ -1,21 +1,37 @@
-use crate::color::Rgb;
+use std::mem;
+use std::ops::Range;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::color::{Alpha, Rgb};
+use crate::display::screenshot::ScreenshotSettings;
 use crate::display::Display;
 use crate::font::FontRenderer;
 use crate::spatial::dimension::Dimensions;
 use crate::spatial::point::Point;
+use crate::spatial::rectangle::Rectangle;
 use crate::spatial::{CellsUnit, PixelsUnit};
+use crate::terminal::cells::line::{Cell, CellWidth};
+use crate::terminal::cells::style::Attributes;
+use crate::terminal::{BACKGROUND_COLOR, FAINT_ALPHA, FONT_COLOR};
+
+const BOLD_MINIMAL_ALPHA: Alpha = Alpha::new(128);
 
 #[derive(Debug)]
 pub struct TerminalRenderer {
-    display: Display,
+    display: Box<dyn Display>,
     font: FontRenderer,
     size: Dimensions<CellsUnit>,
     cell_size: Dimensions<PixelsUnit>,
+    /// Parts of the display drawn since the last flush.
+    damaged: Vec<Rectangle<PixelsUnit>>,
 }
 
 impl TerminalRenderer {
     pub fn new(
-        display: Display,
+        display: Box<dyn Display>,
         font: FontRenderer,
         size: Dimensions<CellsUnit>,
         cell_size: Dimensions<PixelsUnit>,
@@ -25,22 +41,186 @@ impl TerminalRenderer {
             font,
             size,
             cell_size,
+            damaged: Vec::new(),
         }
     }
 
-    pub fn render_character(
+    /// Reloads the display and returns the number of cells fitting in it.
+    pub fn reload_display(&mut self) -> Result<Dimensions<CellsUnit>> {
+        self.display.reload()?;
+        Ok(self.display.size().fit_cells(self.cell_size))
+    }
+
+    pub fn display_event_fd(&self) -> Option<RawFd> {
+        self.display.event_fd()
+    }
+
+    pub fn handle_display_events(&mut self) -> Result<()> {
+        self.display.handle_events()
+    }
+
+    /// Saves the drawn display to a new file, returning its path.
+    pub fn save_screenshot(&self, settings: &ScreenshotSettings) -> Result<PathBuf> {
+        settings.save(self.display.back_buffer())
+    }
+
+    pub fn set_size(&mut self, size: Dimensions<CellsUnit>) {
+        self.size = size;
+    }
+
+    pub fn render_cell(&mut self, cell: &Cell, point: Point<CellsUnit>) {
+        // Spacer is covered by the double width character preceding it.
+        let span = match cell.width() {
+            CellWidth::Single => 1,
+            CellWidth::Double => 2,
+            CellWidth::Spacer => return,
+        };
+        let style = cell.style();
+        let attributes = style.attributes();
+        let mut font_color = style.foreground().to_rgb(FONT_COLOR);
+        let mut background_color = style.background().to_rgb(BACKGROUND_COLOR);
+        if attributes.contains(Attributes::INVERSE) {
+            mem::swap(&mut font_color, &mut background_color);
+        }
+        if attributes.contains(Attributes::FAINT) {
+            font_color = font_color.with_alpha(FAINT_ALPHA).blend(background_color);
+        }
+
+        self.fill_cells(point, span, background_color);
+        if attributes.contains(Attributes::INVISIBLE) {
+            return;
+        }
+
+        self.render_character(cell, point, span, font_color, background_color);
+        if attributes.contains(Attributes::BOLD) {
+            // There is no bold variant of the font, so the glyph is drawn again one pixel to the
+            // right.
+            self.render_shifted_character(cell, point, span, font_color, background_color, 1);
+        }
+        self.render_decorations(attributes, point, span, font_color);
+    }
+
+    /// Returns the size in pixels of the given number of adjacent cells.
+    fn span_size(&self, span: u32) -> Dimensions<PixelsUnit> {
+        Dimensions::new(self.cell_size.width() * span, self.cell_size.height())
+    }
+
+    fn render_decorations(
         &mut self,
-        character: char,
+        attributes: Attributes,
         cell: Point<CellsUnit>,
+        span: u32,
+        color: Rgb,
+    ) {
+        let thickness = (self.cell_size.height() / 16).max(1);
+        let underline_position = self.font.ascent() + thickness;
+        if attributes.contains(Attributes::UNDERLINE) {
+            self.fill_cell_row(cell, span, underline_position, thickness, color);
+        }
+        if attributes.contains(Attributes::DOUBLE_UNDERLINE) {
+            self.fill_cell_row(cell, span, underline_position, thickness, color);
+            self.fill_cell_row(
+                cell,
+                span,
+                underline_position + 2 * thickness,
+                thickness,
+                color,
+            );
+        }
+        if attributes.contains(Attributes::CURLY_UNDERLINE) {
+            self.render_curly_line(cell, span, underline_position, thickness, color);
+        }
+        if attributes.contains(Attributes::STRIKETHROUGH) {
+            let position = self.font.ascent() * 2 / 3;
+            self.fill_cell_row(cell, span, position, thickness, color);
+        }
+    }
+
+    fn fill_cell_row(
+        &mut self,
+        cell: Point<CellsUnit>,
+        span: u32,
+        vertical_distance: u32,
+        thickness: u32,
+        color: Rgb,
+    ) {
+        let cell_origin = cell.to_pixels(self.cell_size);
+        let end = (vertical_distance + thickness).min(self.cell_size.height());
+        for vertical_distance in vertical_distance.min(end)..end {
+            for horizontal_distance in 0..self.span_size(span).width() {
+                let point = Point::new(horizontal_distance, vertical_distance);
+                let point = point.with_origin(cell_origin);
+                self.display.pixel_mut(point).set_rgb(color);
+            }
+        }
+    }
+
+    fn render_curly_line(
+        &mut self,
+        cell: Point<CellsUnit>,
+        span: u32,
+        vertical_distance: u32,
+        thickness: u32,
+        color: Rgb,
+    ) {
+        const WAVE: [u32; 4] = [0, 1, 2, 1];
+
+        let cell_origin = cell.to_pixels(self.cell_size);
+        // Phase of the wave depends on the absolute position, so it is continuous between cells.
+        let phase = cell_origin.horizontal_distance() / thickness;
+        for horizontal_distance in 0..self.span_size(span).width() {
+            let offset = WAVE[((phase + horizontal_distance / thickness) % 4) as usize];
+            let start = vertical_distance + offset * thickness;
+            let end = (start + thickness).min(self.cell_size.height());
+            for vertical_distance in start.min(end)..end {
+                let point = Point::new(horizontal_distance, vertical_distance);
+                let point = point.with_origin(cell_origin);
+                self.display.pixel_mut(point).set_rgb(color);
+            }
+        }
+    }
+
+    /// Renders the character of the cell together with its marks over the given number of cells
+    /// starting at the given one.
+    pub fn render_character(
+        &mut self,
+        cell: &Cell,
+        point: Point<CellsUnit>,
+        span: u32,
         font_color: Rgb,
         background_color: Rgb,
     ) {
-        debug_assert!(self.size.contains(cell));
+        self.render_shifted_character(cell, point, span, font_color, background_color, 0);
+    }
 
-        let cell_origin = cell.to_pixels(self.cell_size);
-        let raster = self.font.create_raster(character);
+    fn render_shifted_character(
+        &mut self,
+        cell: &Cell,
+        point: Point<CellsUnit>,
+        span: u32,
+        font_color: Rgb,
+        background_color: Rgb,
+        shift: u32,
+    ) {
+        debug_assert!(self.size.contains(point));
+        let Some(character) = cell.character() else {
+            return;
+        };
+
+        let span_size = self.span_size(span);
+        let cell_origin = point.to_pixels(self.cell_size);
+        let raster = self.font.create_cluster_raster(character, cell.marks());
         for (point, alpha) in raster {
-            debug_assert!(self.cell_size.contains(point));
+            let point = point.shifted(shift as i32, 0);
+            // Shifted copy, glyphs wider than the cell and stacked marks reach past it, so the
+            // raster is clipped to it.
+            if !span_size.contains(point) {
+                continue;
+            }
+            // Shifted copy only thickens the strokes, so it must not lighten already drawn pixels.
+            if shift > 0 && alpha < BOLD_MINIMAL_ALPHA {
+                continue;
+            }
 
             let display_point = point.with_origin(cell_origin);
             let mut display_pixel = self.display.pixel_mut(display_point);
@@ -51,10 +231,16 @@ impl TerminalRenderer {
     }
 
     pub fn fill_cell(&mut self, cell: Point<CellsUnit>, color: Rgb) {
+        self.fill_cells(cell, 1, color);
+    }
+
+    /// Fills the given number of cells starting at the given one.
+    fn fill_cells(&mut self, cell: Point<CellsUnit>, span: u32, color: Rgb) {
         debug_assert!(self.size.contains(cell));
 
         let cell_origin = cell.to_pixels(self.cell_size);
-        for horizontal_distance in 0..self.cell_size.width() {
+        self.damage(Rectangle::new(cell_origin, self.span_size(span)));
+        for horizontal_distance in 0..self.span_size(span).width() {
             for vertical_distance in 0..self.cell_size.height() {
                 let point = Point::new(horizontal_distance, vertical_distance);
                 let point = point.with_origin(cell_origin);
@@ -66,6 +252,8 @@ impl TerminalRenderer {
 
     pub fn fill_all(&mut self, color: Rgb) {
         let size = self.display.size();
+        self.damaged.clear();
+        self.damage(Rectangle::new(Point::new(0, 0), size));
         for horizontal_distance in 0..size.width() {
             for vertical_distance in 0..size.height() {
                 let point = Point::new(horizontal_distance, vertical_distance);
@@ -74,4 +262,35 @@ impl TerminalRenderer {
             }
         }
     }
+
+    /// Moves pixels of the lines in the region up by the given number of lines, or down if it is
+    /// negative. Lines exposed at the other end keep their old content until they are rendered.
+    pub fn scroll(&mut self, region: Range<u32>, lines: i32) {
+        let cell_height = self.cell_size.height();
+        let rows = region.start * cell_height..region.end * cell_height;
+        self.display
+            .scroll(rows.clone(), lines * cell_height as i32);
+
+        let width = self.display.size().width();
+        let size = Dimensions::new(width, rows.len() as u32);
+        self.damage(Rectangle::new(Point::new(0, rows.start), size));
+    }
+
+    /// Copies everything drawn since the last flush to the display.
+    pub fn flush(&mut self) -> Result<()> {
+        self.display.flush(&self.damaged)?;
+        self.damaged.clear();
+        Ok(())
+    }
+
+    fn damage(&mut self, rectangle: Rectangle<PixelsUnit>) {
+        // Cells are mostly drawn left to right, so neighbours are joined into a single rectangle.
+        if let Some(last) = self.damaged.last_mut() {
+            if let Some(joined) = last.joined(rectangle) {
+                *last = joined;
+                return;
+            }
+        }
+        self.damaged.push(rectangle);
+    }
 }
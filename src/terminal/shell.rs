@@ -3,16 +3,26 @@ use std::os::unix::io::RawFd;
 
 use anyhow::Result;
 use nix::errno::Errno;
+use nix::fcntl::{self, FcntlArg, OFlag};
+use nix::pty::Winsize;
 use nix::unistd::{ForkResult, Pid};
 use nix::{libc, pty, unistd};
 
 use crate::spatial::dimension::Dimensions;
 use crate::spatial::CellsUnit;
 
+nix::ioctl_write_ptr_bad!(set_window_size, libc::TIOCSWINSZ, Winsize);
+
+/// Limit of bytes waiting to be written to the shell. Shell which does not read its input, while
+/// its output keeps requesting reports, would otherwise grow the queue without bound.
+const MAX_PENDING_INPUT: usize = 1 << 20;
+
 #[derive(Debug)]
 pub struct Shell {
     master_fd: RawFd,
     pid_fd: RawFd,
+    /// Bytes not yet accepted by the pseudoterminal, which are written once it is writable again.
+    pending_input: Vec<u8>,
 }
 
 impl Shell {
@@ -28,8 +38,14 @@ impl Shell {
         match result.fork_result {
             ForkResult::Parent { child } => {
                 let master_fd = result.master;
+                // Writes must not block the event loop while the shell is not reading its input.
+                fcntl::fcntl(master_fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
                 let pid_fd = Self::pidfd_open(child)?;
-                Ok(Self { master_fd, pid_fd })
+                Ok(Self {
+                    master_fd,
+                    pid_fd,
+                    pending_input: Vec::new(),
+                })
             }
             ForkResult::Child => {
                 for fd in fds_to_close {
@@ -46,6 +62,49 @@ impl Shell {
         Errno::result(result).map(|result| result as RawFd)
     }
 
+    /// Sets size of the pseudoterminal, which sends SIGWINCH to the shell.
+    pub fn resize(&self, terminal_size: Dimensions<CellsUnit>) -> Result<()> {
+        let size = terminal_size.into();
+        // SAFETY: Master fd is a pseudoterminal and size is a valid pointer to Winsize.
+        unsafe { set_window_size(self.master_fd, &size)? };
+        Ok(())
+    }
+
+    /// Queues the bytes after any pending ones and writes as many of them as the pseudoterminal
+    /// accepts. Bytes not fitting into the queue are dropped as a whole, so the shell never gets a
+    /// partial sequence. Returns true if some bytes are still pending, see [`Shell::flush`].
+    pub fn write(&mut self, bytes: &[u8]) -> bool {
+        if self.pending_input.len() + bytes.len() > MAX_PENDING_INPUT {
+            log::warn!(
+                "Shell does not read its input, dropping {} bytes while {} are pending",
+                bytes.len(),
+                self.pending_input.len()
+            );
+            return !self.pending_input.is_empty();
+        }
+        self.pending_input.extend_from_slice(bytes);
+        self.flush()
+    }
+
+    /// Writes pending bytes until the pseudoterminal would block. Write errors are only logged, as
+    /// the shell may have already exited. Returns true if some bytes are still pending.
+    pub fn flush(&mut self) -> bool {
+        let mut written = 0;
+        while written < self.pending_input.len() {
+            match unistd::write(self.master_fd, &self.pending_input[written..]) {
+                Ok(bytes_written) => written += bytes_written,
+                Err(Errno::EAGAIN) => break,
+                Err(Errno::EINTR) => {}
+                Err(error) => {
+                    log::warn!("Writing to shell failed: {}", error);
+                    written = self.pending_input.len();
+                }
+            }
+        }
+        self.pending_input.drain(..written);
+        !self.pending_input.is_empty()
+    }
+
     pub fn master_fd(&self) -> RawFd {
         self.master_fd
     }
@@ -54,3 +113,69 @@ impl Shell {
         self.pid_fd
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns shell writing to a pipe with a small buffer, together with the read end of the pipe
+    /// and size of its buffer.
+    fn shell_with_pipe() -> (Shell, RawFd, usize) {
+        let (read_fd, write_fd) = unistd::pipe().unwrap();
+        fcntl::fcntl(write_fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).unwrap();
+        let pipe_size = fcntl::fcntl(write_fd, FcntlArg::F_SETPIPE_SZ(4096)).unwrap() as usize;
+        let shell = Shell {
+            master_fd: write_fd,
+            pid_fd: -1,
+            pending_input: Vec::new(),
+        };
+        (shell, read_fd, pipe_size)
+    }
+
+    fn read_all(fd: RawFd, length: usize) -> Vec<u8> {
+        let mut bytes = vec![0; length];
+        let mut read = 0;
+        while read < length {
+            read += unistd::read(fd, &mut bytes[read..]).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_write_queues_until_writable() {
+        let (mut shell, read_fd, pipe_size) = shell_with_pipe();
+        let bytes: Vec<u8> = (0..pipe_size + 100).map(|index| index as u8).collect();
+        // Pipe accepts only a part of the bytes and would block on the rest.
+        assert!(shell.write(&bytes));
+        assert!(shell.write(b"end"));
+        assert_eq!(103, shell.pending_input.len());
+        assert!(shell.flush());
+
+        // Reading makes the pipe writable again, which the event loop learns from EPOLLOUT.
+        let mut written = read_all(read_fd, pipe_size);
+        assert!(!shell.flush());
+        written.extend(read_all(read_fd, 103));
+        assert_eq!([bytes.as_slice(), b"end"].concat(), written);
+
+        unistd::close(read_fd).unwrap();
+        unistd::close(shell.master_fd).unwrap();
+    }
+
+    #[test]
+    fn test_write_drops_input_over_limit() {
+        let (mut shell, read_fd, pipe_size) = shell_with_pipe();
+        assert!(!shell.write(&vec![0; MAX_PENDING_INPUT + 1]));
+        assert!(shell.pending_input.is_empty());
+
+        assert!(shell.write(&vec![0; MAX_PENDING_INPUT]));
+        assert_eq!(MAX_PENDING_INPUT - pipe_size, shell.pending_input.len());
+        assert!(shell.write(&vec![1; pipe_size + 1]));
+        assert_eq!(MAX_PENDING_INPUT - pipe_size, shell.pending_input.len());
+        // Bytes fitting into the queue exactly are still kept.
+        assert!(shell.write(&vec![1; pipe_size]));
+        assert_eq!(MAX_PENDING_INPUT, shell.pending_input.len());
+
+        unistd::close(read_fd).unwrap();
+        unistd::close(shell.master_fd).unwrap();
+    }
+}